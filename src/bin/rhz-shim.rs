@@ -0,0 +1,88 @@
+//! Tiny launcher copied into `~/.rhiza/bin` as `<name>.exe` by `rhz run`.
+//!
+//! It reads the `<name>.shim` file next to it and runs the target it describes, forwarding
+//! its own arguments, stdio and (when waiting) the exit code.
+//!
+//! See src/shim_file.rs for its format.
+
+use std::env;
+use std::fs;
+use std::process::{exit, Command};
+
+// Shared with rhz, which writes the shims
+#[allow(dead_code)]
+#[path = "../shim_file.rs"]
+mod shim_file;
+
+use shim_file::Shim;
+
+fn main() {
+    match run() {
+        Ok(code) => exit(code),
+        Err(err) => {
+            eprintln!("rhz-shim: {}", err);
+            exit(1);
+        }
+    }
+}
+
+fn run() -> Result<i32, String> {
+    let exe = env::current_exe().map_err(|e| format!("can't locate itself: {}", e))?;
+    let shim_path = exe.with_extension("shim");
+    let content = fs::read_to_string(&shim_path)
+        .map_err(|e| format!("can't read {}: {}", shim_path.display(), e))?;
+    let shim =
+        Shim::parse(&content).ok_or_else(|| format!("{} has no target", shim_path.display()))?;
+
+    let mut command = Command::new(&shim.target);
+    if let Some(args) = &shim.args {
+        push_raw_args(&mut command, args);
+    }
+    command.args(env::args_os().skip(1));
+    if let Some(dir) = &shim.dir {
+        command.current_dir(dir);
+    }
+
+    let mut child = command
+        .spawn()
+        .map_err(|e| format!("can't start {}: {}", shim.target, e))?;
+    if !shim.wait {
+        return Ok(0);
+    }
+
+    // Let the child decide what Ctrl+C means, we only wait for it to exit
+    ignore_ctrl_c();
+    let status = child
+        .wait()
+        .map_err(|e| format!("failed waiting on {}: {}", shim.target, e))?;
+    Ok(status.code().unwrap_or(1))
+}
+
+#[cfg(windows)]
+fn push_raw_args(command: &mut Command, args: &str) {
+    // The args are already quoted the way the target expects them
+    use std::os::windows::process::CommandExt;
+    command.raw_arg(args);
+}
+
+#[cfg(not(windows))]
+fn push_raw_args(command: &mut Command, args: &str) {
+    command.args(args.split_whitespace());
+}
+
+#[cfg(windows)]
+fn ignore_ctrl_c() {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetConsoleCtrlHandler(
+            handler: Option<unsafe extern "system" fn(u32) -> i32>,
+            add: i32,
+        ) -> i32;
+    }
+    unsafe {
+        SetConsoleCtrlHandler(None, 1);
+    }
+}
+
+#[cfg(not(windows))]
+fn ignore_ctrl_c() {}
@@ -0,0 +1,232 @@
+use clap::Command;
+
+use crate::installer::Config;
+
+pub const SHELLS: [&str; 5] = ["bash", "zsh", "fish", "powershell", "elvish"];
+
+/// Value name of arguments completed with the linked command names
+pub const COMMAND_VALUE: &str = "COMMAND";
+/// Value name of arguments completed with the profile names
+pub const PROFILE_VALUE: &str = "PROFILE";
+
+/// The registration script for a shell. Every script calls back into
+/// `rhz __complete --current=<word> -- <previous words>`, so the candidates always reflect
+/// the current config.
+pub fn script(shell: &str) -> Option<&'static str> {
+    let script = match shell {
+        "bash" => {
+            r#"_rhz() {
+    local IFS=$'\n'
+    COMPREPLY=($(rhz __complete "--current=${COMP_WORDS[COMP_CWORD]}" -- "${COMP_WORDS[@]:1:COMP_CWORD-1}" 2>/dev/null | while IFS= read -r line; do printf '%q\n' "$line"; done))
+}
+complete -o default -F _rhz rhz
+"#
+        }
+        "zsh" => {
+            r#"#compdef rhz
+_rhz() {
+    local -a candidates
+    candidates=(${(f)"$(rhz __complete "--current=${words[CURRENT]}" -- "${(@)words[2,CURRENT-1]}" 2>/dev/null)"})
+    compadd -a candidates
+}
+compdef _rhz rhz
+"#
+        }
+        "fish" => {
+            r#"complete -c rhz -f -a '(rhz __complete --current=(commandline -ct) -- (commandline -opc)[2..-1] 2>/dev/null)'
+"#
+        }
+        "powershell" => {
+            r#"Register-ArgumentCompleter -Native -CommandName rhz -ScriptBlock {
+    param($wordToComplete, $commandAst, $cursorPosition)
+    $words = @($commandAst.CommandElements | Select-Object -Skip 1 |
+        Where-Object { $_.Extent.EndOffset -lt $cursorPosition } | ForEach-Object { "$_" })
+    rhz __complete "--current=$wordToComplete" -- @words 2>$null | ForEach-Object {
+        $text = if ($_ -match '\s') { "'$_'" } else { $_ }
+        [System.Management.Automation.CompletionResult]::new($text, $_, 'ParameterValue', $_)
+    }
+}
+"#
+        }
+        "elvish" => {
+            r#"set edit:completion:arg-completer[rhz] = {|@words|
+    rhz __complete --current=$words[-1] -- $@words[1..-1]
+}
+"#
+        }
+        _ => return None,
+    };
+    Some(script)
+}
+
+/// Candidates for `current`, given the words typed before it (without `rhz` itself)
+pub fn complete(mut cli: Command, config: &Config, words: &[String], current: &str) -> Vec<String> {
+    // Propagates global arguments down to the subcommands
+    cli.build();
+    let mut command = &cli;
+    let mut positional = 0;
+    let mut skip_value = false;
+
+    for word in words {
+        // The value of the previous option
+        if skip_value {
+            skip_value = false;
+            continue;
+        }
+        if let Some(long) = word.strip_prefix("--") {
+            skip_value = !long.contains('=') && takes_value(command, long);
+            continue;
+        }
+        if word.starts_with('-') {
+            continue;
+        }
+        match command.find_subcommand(word) {
+            Some(sub) => {
+                command = sub;
+                positional = 0;
+            }
+            None => positional += 1,
+        }
+    }
+
+    let mut candidates = Vec::new();
+
+    if let Some(previous) = words.last().and_then(|word| word.strip_prefix("--")) {
+        if takes_value(command, previous) {
+            if let Some(arg) = command
+                .get_arguments()
+                .find(|arg| arg.get_long() == Some(previous))
+            {
+                candidates.extend(arg_values(arg, config));
+            }
+            return filter(candidates, current);
+        }
+    }
+
+    if current.starts_with('-') {
+        for arg in command.get_arguments() {
+            if let Some(long) = arg.get_long() {
+                if !arg.is_hide_set() {
+                    candidates.push(format!("--{}", long));
+                }
+            }
+        }
+        return filter(candidates, current);
+    }
+
+    if positional == 0 {
+        for sub in command.get_subcommands() {
+            if !sub.is_hide_set() {
+                candidates.push(sub.get_name().to_string());
+            }
+        }
+    }
+    if let Some(arg) = command.get_positionals().nth(positional) {
+        candidates.extend(arg_values(arg, config));
+    }
+
+    filter(candidates, current)
+}
+
+fn takes_value(command: &Command, long: &str) -> bool {
+    command
+        .get_arguments()
+        .find(|arg| arg.get_long() == Some(long))
+        .is_some_and(|arg| arg.get_action().takes_values())
+}
+
+fn arg_values(arg: &clap::Arg, config: &Config) -> Vec<String> {
+    let value_name = arg
+        .get_value_names()
+        .and_then(|names| names.first())
+        .map(|name| name.as_str());
+
+    match value_name {
+        Some(COMMAND_VALUE) => config.applied_commands().into_keys().collect(),
+        Some(PROFILE_VALUE) => config.profile_names(),
+        _ => arg
+            .get_possible_values()
+            .iter()
+            .filter(|value| !value.is_hide_set())
+            .map(|value| value.get_name().to_string())
+            .collect(),
+    }
+}
+
+fn filter(mut candidates: Vec<String>, current: &str) -> Vec<String> {
+    let current = current.to_lowercase();
+    candidates.retain(|candidate| candidate.to_lowercase().starts_with(&current));
+    candidates.sort();
+    candidates.dedup();
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::installer::{Entry, Profile};
+
+    fn complete_words(config: &Config, words: &[&str], current: &str) -> Vec<String> {
+        let words: Vec<String> = words.iter().map(|word| word.to_string()).collect();
+        complete(crate::cli(), config, &words, current)
+    }
+
+    fn config() -> Config {
+        let mut config = Config::default();
+        for key in ["firefox", "fd", "code"] {
+            config
+                .commands
+                .insert(key.to_string(), Entry::new(format!("C:\\{}.exe", key)));
+        }
+        let mut commands = std::collections::HashMap::new();
+        commands.insert("steam".to_string(), Entry::new("C:\\steam.exe".to_string()));
+        config
+            .profiles
+            .insert("gaming".to_string(), Profile { commands });
+        config
+    }
+
+    #[test]
+    fn completes_subcommands_and_options() {
+        let config = config();
+        assert_eq!(complete_words(&config, &[], "ru"), ["run"]);
+        // Hidden subcommands aren't offered
+        assert!(complete_words(&config, &[], "__").is_empty());
+        assert_eq!(complete_words(&config, &["profile"], "c"), ["create"]);
+        assert_eq!(complete_words(&config, &["run"], "--sc"), ["--scope"]);
+    }
+
+    #[test]
+    fn completes_option_values() {
+        let config = config();
+        assert_eq!(
+            complete_words(&config, &["run", "--scope"], ""),
+            ["machine", "user"]
+        );
+        assert_eq!(
+            complete_words(&config, &["add", "--launch"], "d"),
+            ["detached"]
+        );
+        // Once the value is given, the positionals are completed again
+        assert_eq!(
+            complete_words(&config, &["launch", "--json", "code"], "a"),
+            ["attached", "auto"]
+        );
+    }
+
+    #[test]
+    fn completes_command_and_profile_names() {
+        let config = config();
+        assert_eq!(complete_words(&config, &["rm"], "f"), ["fd", "firefox"]);
+        assert_eq!(
+            complete_words(&config, &["rm", "--scope", "user"], "c"),
+            ["code"]
+        );
+        // Only the active profiles' commands
+        assert!(complete_words(&config, &["rm"], "st").is_empty());
+        assert_eq!(
+            complete_words(&config, &["profile", "use"], ""),
+            ["default", "gaming"]
+        );
+    }
+}
@@ -0,0 +1,523 @@
+use colored::Colorize;
+use serde_derive::Serialize;
+use serde_json::json;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::installer::{self, Config, Scope};
+use crate::path_env::{self, normalize, RegistryEnv};
+use crate::paths;
+use crate::shim::Shim;
+use crate::start_menu;
+use crate::worker;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum Fix {
+    Setup,
+    ResetConfig,
+    RegenerateShims,
+    DedupePath,
+    SyncStartMenu,
+    RemovePanicLog,
+}
+
+#[derive(Serialize)]
+struct Problem {
+    severity: Severity,
+    message: String,
+    fix: Option<Fix>,
+    /// The scope the fix applies to
+    scope: Scope,
+}
+
+impl Problem {
+    fn new(severity: Severity, message: String, fix: Option<Fix>) -> Self {
+        Problem {
+            severity,
+            message,
+            fix,
+            scope: Scope::User,
+        }
+    }
+}
+
+pub fn run(fix: bool) -> io::Result<()> {
+    let mut problems = diagnose();
+    problems.sort_by_key(|problem| problem.severity);
+    crate::report::emit(&json!({ "problems": problems, "fixing": fix }));
+
+    if problems.is_empty() {
+        say!("{}", "No problems found!".green().bold());
+        return Ok(());
+    }
+
+    report(&problems);
+
+    let mut fixes: Vec<(Fix, Scope)> = Vec::new();
+    for problem in &problems {
+        if let Some(f) = problem.fix {
+            if !fixes.contains(&(f, problem.scope)) {
+                fixes.push((f, problem.scope));
+            }
+        }
+    }
+
+    if fixes.is_empty() {
+        return Ok(());
+    }
+    if !fix {
+        say!(
+            "{}",
+            format!(
+                "Do 'rhz doctor --fix' to repair {} of them",
+                problems.iter().filter(|p| p.fix.is_some()).count()
+            )
+            .purple()
+            .bold()
+        );
+        return Ok(());
+    }
+
+    let elevated = installer::is_elevated();
+    if !elevated && fixes.iter().any(|(_, scope)| *scope == Scope::Machine) {
+        say!(
+            "{}",
+            "Skipping the machine scope fixes, they need an elevated shell".yellow()
+        );
+    }
+    for (f, scope) in fixes {
+        if scope == Scope::User || elevated {
+            apply(f, scope)?;
+        }
+    }
+    say!("{}", "Done fixing".purple());
+
+    Ok(())
+}
+
+fn report(problems: &[Problem]) {
+    for (severity, group) in grouped(problems) {
+        let header = match severity {
+            Severity::Error => format!("Errors ({})", group.len()).red().bold(),
+            Severity::Warning => format!("Warnings ({})", group.len()).yellow().bold(),
+            Severity::Info => format!("Info ({})", group.len()).blue().bold(),
+        };
+        say!("{}", header);
+        for problem in group {
+            let fixable = if problem.fix.is_some() {
+                " (fixable)".green().to_string()
+            } else {
+                String::new()
+            };
+            let scope = match problem.scope {
+                Scope::User => String::new(),
+                Scope::Machine => "[machine] ".dimmed().to_string(),
+            };
+            say!("  - {}{}{}", scope, problem.message, fixable);
+        }
+    }
+}
+
+/// The problems by severity, errors first, leaving out the empty groups
+fn grouped(problems: &[Problem]) -> Vec<(Severity, Vec<&Problem>)> {
+    [Severity::Error, Severity::Warning, Severity::Info]
+        .into_iter()
+        .map(|severity| {
+            let group: Vec<&Problem> = problems
+                .iter()
+                .filter(|problem| problem.severity == severity)
+                .collect();
+            (severity, group)
+        })
+        .filter(|(_, group)| !group.is_empty())
+        .collect()
+}
+
+/// Checks the config, then the user scope, and the machine scope too when a command is
+/// installed there or it was set up before
+fn diagnose() -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    let config = match check_config(&paths::config_file()) {
+        Ok(config) => config.applied(),
+        Err(problem) => return vec![problem],
+    };
+    problems.extend(check_targets(&config));
+
+    let machine = config
+        .commands
+        .values()
+        .any(|entry| entry.scope == Scope::Machine)
+        || Path::new(&Scope::Machine.bin_dir()).exists();
+    problems.extend(diagnose_scope(&config, Scope::User));
+    if machine {
+        problems.extend(diagnose_scope(&config, Scope::Machine));
+    }
+
+    // Panic log
+    let panic_log = paths::panic_log();
+    if let Ok(metadata) = fs::metadata(&panic_log) {
+        let modified = metadata
+            .modified()
+            .map(|time| {
+                chrono::DateTime::<chrono::Local>::from(time)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string()
+            })
+            .unwrap_or_else(|_| "an unknown date".to_string());
+        problems.push(Problem::new(
+            Severity::Info,
+            format!("{} exists, last panic at {}", panic_log.display(), modified),
+            Some(Fix::RemovePanicLog),
+        ));
+    }
+
+    problems
+}
+
+/// Reads the config, a missing one is created by the setup and an unreadable one put aside
+fn check_config(file: &Path) -> Result<Config, Problem> {
+    installer::read_config_from(file).map_err(|err| {
+        let fix = if err.kind() == io::ErrorKind::NotFound {
+            Fix::Setup
+        } else {
+            Fix::ResetConfig
+        };
+        Problem::new(
+            Severity::Error,
+            format!("Can't read {}: {}", file.display(), err),
+            Some(fix),
+        )
+    })
+}
+
+/// Commands whose target is gone
+fn check_targets(config: &Config) -> Vec<Problem> {
+    let mut keys: Vec<&String> = config.commands.keys().collect();
+    keys.sort();
+    keys.into_iter()
+        .filter(|key| !Path::new(&config.commands[*key].target).exists())
+        .map(|key| {
+            Problem::new(
+                Severity::Warning,
+                format!(
+                    "'{}' points to {}, which no longer exists",
+                    key, config.commands[key].target
+                ),
+                None,
+            )
+        })
+        .collect()
+}
+
+/// The shims, PATH and Start Menu of a scope
+fn diagnose_scope(config: &Config, scope: Scope) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let rhiza_bin = scope.bin_dir();
+
+    // Shims
+    if let Ok(entries) = fs::read_dir(&rhiza_bin) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let content = match path.extension().and_then(|ext| ext.to_str()) {
+                Some("bat") | Some("shim") => fs::read_to_string(&path).unwrap_or_default(),
+                _ => continue,
+            };
+            let missing = match Shim::parse(&content) {
+                // The target itself and, for scripts and `start` shims, the file it opens
+                Some(shim) => {
+                    !Path::new(&shim.target).exists()
+                        || shim
+                            .args
+                            .and_then(|args| quoted_path(&args))
+                            .is_some_and(|path| !Path::new(&path).exists())
+                }
+                None => quoted_path(&content).is_none_or(|path| !Path::new(&path).exists()),
+            };
+            if missing {
+                problems.push(Problem::new(
+                    Severity::Error,
+                    format!("{} points to a missing file", path.display()),
+                    Some(Fix::RegenerateShims),
+                ));
+            }
+        }
+    }
+
+    // PATH
+    match installer::read_path(scope) {
+        Ok(current_path) => problems.extend(check_path(config, &rhiza_bin, &current_path)),
+        Err(err) => problems.push(Problem::new(
+            Severity::Error,
+            format!("Can't read the {} PATH: {}", scope.name(), err),
+            None,
+        )),
+    }
+
+    // Start Menu
+    if let Ok(changes) = start_menu::changes(config, scope) {
+        let out_of_sync = changes.copy.len() + changes.stale.len();
+        if out_of_sync > 0 {
+            problems.push(Problem::new(
+                Severity::Warning,
+                format!(
+                    "The Start Menu rhiza folder is out of sync with src ({} files differ)",
+                    out_of_sync
+                ),
+                Some(Fix::SyncStartMenu),
+            ));
+        }
+    }
+
+    for problem in &mut problems {
+        problem.scope = scope;
+    }
+    problems
+}
+
+/// A PATH without the rhiza bin, too long or with duplicate entries
+fn check_path(config: &Config, rhiza_bin: &str, current_path: &str) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    if !current_path
+        .split(';')
+        .any(|entry| normalize(entry) == normalize(rhiza_bin))
+    {
+        problems.push(Problem::new(
+            Severity::Error,
+            format!("{} is not in the PATH", rhiza_bin),
+            Some(Fix::Setup),
+        ));
+    }
+
+    let limits = &config.path_limits;
+    let expanded = path_env::expanded_len(current_path);
+    if expanded > limits.warn {
+        problems.push(Problem::new(
+            Severity::Warning,
+            format!(
+                "The PATH is {} characters expanded, some tools truncate it past {}",
+                expanded, limits.warn
+            ),
+            None,
+        ));
+    }
+
+    let mut seen = HashSet::new();
+    for entry in current_path.split(';').filter(|entry| !entry.is_empty()) {
+        if !seen.insert(normalize(entry)) {
+            problems.push(Problem::new(
+                Severity::Warning,
+                format!("'{}' appears more than once in the PATH", entry),
+                Some(Fix::DedupePath),
+            ));
+        }
+    }
+    problems
+}
+
+fn apply(fix: Fix, scope: Scope) -> io::Result<()> {
+    match fix {
+        Fix::Setup => match scope {
+            Scope::User => {
+                installer::check()?;
+            }
+            Scope::Machine => {
+                path_env::add(&mut RegistryEnv(scope), &scope.bin_dir())?;
+            }
+        },
+        Fix::ResetConfig => reset_config()?,
+        Fix::RegenerateShims => {
+            let config = installer::read_config()?.applied();
+            let rhiza_src = scope.src_dir();
+            let rhiza_bin = scope.bin_dir();
+            fs::create_dir_all(&rhiza_src)?;
+            worker::generate_shims(&config, scope, &rhiza_src, &rhiza_bin)?;
+        }
+        Fix::DedupePath => {
+            path_env::dedupe(&mut RegistryEnv(scope))?;
+        }
+        Fix::SyncStartMenu => {
+            let config = installer::read_config()?.applied();
+            start_menu::sync(&config, scope)?;
+            say!("{}", "Synced the Start Menu".green());
+        }
+        Fix::RemovePanicLog => {
+            fs::remove_file(paths::panic_log())?;
+            say!("{}", "Removed panic.log".green());
+        }
+    }
+
+    Ok(())
+}
+
+/// Moves an unreadable config.json to `<rhiza home>/backups` and starts over from an empty one
+fn reset_config() -> io::Result<()> {
+    let backup = back_up_config(&paths::config_file(), &paths::backups_dir())?;
+    installer::check()?;
+    say!(
+        "{}",
+        format!("Moved the old config to {}", backup.display()).green()
+    );
+    Ok(())
+}
+
+/// Moves `file` into `backups` as `config-<time>.json`, returning where it went
+fn back_up_config(file: &Path, backups: &Path) -> io::Result<PathBuf> {
+    fs::create_dir_all(backups)?;
+    let id = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    let backup = backups.join(format!("config-{}.json", id));
+    fs::rename(file, &backup)?;
+    Ok(backup)
+}
+
+// The last quoted absolute path, e.g the target of a `start /B "" "<target>" %*` shim
+fn quoted_path(content: &str) -> Option<String> {
+    content
+        .split('"')
+        .skip(1)
+        .step_by(2)
+        .filter(|quoted| quoted.contains(":\\") || quoted.starts_with("\\\\"))
+        .last()
+        .map(|quoted| quoted.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::installer::Entry;
+
+    fn messages(problems: &[Problem]) -> Vec<&str> {
+        problems
+            .iter()
+            .map(|problem| problem.message.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn missing_targets_are_warnings() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = dir.path().join("app.exe");
+        fs::write(&app, "MZ").unwrap();
+        let gone = dir.path().join("gone.exe");
+
+        let mut config = Config::default();
+        for (key, target) in [("app", &app), ("gone", &gone)] {
+            let entry = Entry::new(target.display().to_string());
+            config.commands.insert(key.to_string(), entry);
+        }
+        let file = dir.path().join("config.json");
+        fs::write(&file, serde_json::to_string(&config).unwrap()).unwrap();
+
+        let config = check_config(&file).ok().unwrap();
+        let problems = check_targets(&config);
+        assert_eq!(
+            messages(&problems),
+            [format!(
+                "'gone' points to {}, which no longer exists",
+                gone.display()
+            )]
+        );
+        assert_eq!(problems[0].severity, Severity::Warning);
+        assert_eq!(problems[0].fix, None);
+    }
+
+    #[test]
+    fn unreadable_configs_are_backed_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("config.json");
+        let backups = dir.path().join("backups");
+
+        let problem = check_config(&file).err().unwrap();
+        assert_eq!(problem.fix, Some(Fix::Setup));
+
+        fs::write(&file, "{ not json").unwrap();
+        let problem = check_config(&file).err().unwrap();
+        assert_eq!(problem.severity, Severity::Error);
+        assert_eq!(problem.fix, Some(Fix::ResetConfig));
+
+        let backup = back_up_config(&file, &backups).unwrap();
+        assert!(backup.starts_with(&backups));
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "{ not json");
+        assert!(!file.exists());
+        // Nothing to set aside anymore, the setup makes a new one
+        assert_eq!(check_config(&file).err().unwrap().fix, Some(Fix::Setup));
+        assert!(back_up_config(&file, &backups).is_err());
+    }
+
+    #[test]
+    fn bad_path_entries_are_reported() {
+        let config = Config::default();
+        let bin = "C:\\Users\\me\\.rhiza\\bin";
+        assert!(check_path(&config, bin, &format!("C:\\Windows;{}", bin)).is_empty());
+        // Case, slashes and a trailing separator don't matter
+        assert!(check_path(&config, bin, "c:/users/ME/.rhiza/bin/").is_empty());
+
+        let problems = check_path(&config, bin, "C:\\Windows;C:\\tools;c:\\windows\\");
+        assert_eq!(
+            messages(&problems),
+            [
+                format!("{} is not in the PATH", bin).as_str(),
+                "'c:\\windows\\' appears more than once in the PATH",
+            ]
+        );
+        assert_eq!(problems[0].fix, Some(Fix::Setup));
+        assert_eq!(problems[1].fix, Some(Fix::DedupePath));
+
+        let mut config = Config::default();
+        config.path_limits.warn = 20;
+        let problems = check_path(&config, bin, &format!("{};C:\\Windows", bin));
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].severity, Severity::Warning);
+        assert!(problems[0].message.contains("past 20"));
+    }
+
+    #[test]
+    fn problems_are_grouped_by_severity() {
+        let problems = [
+            Problem::new(Severity::Info, "panic".to_string(), None),
+            Problem::new(Severity::Error, "bin".to_string(), Some(Fix::Setup)),
+            Problem::new(Severity::Error, "shim".to_string(), None),
+        ];
+        let groups: Vec<(Severity, Vec<&str>)> = grouped(&problems)
+            .into_iter()
+            .map(|(severity, group)| {
+                let messages = group.iter().map(|p| p.message.as_str()).collect();
+                (severity, messages)
+            })
+            .collect();
+        assert_eq!(
+            groups,
+            [
+                (Severity::Error, vec!["bin", "shim"]),
+                (Severity::Info, vec!["panic"]),
+            ]
+        );
+        assert!(grouped(&[]).is_empty());
+    }
+
+    #[test]
+    fn quoted_paths_are_the_last_absolute_one() {
+        assert_eq!(
+            quoted_path("start /B \"\" \"C:\\apps\\app.exe\" %*").as_deref(),
+            Some("C:\\apps\\app.exe")
+        );
+        assert_eq!(
+            quoted_path("\"C:\\py\\python.exe\" \"\\\\server\\share\\tool.py\" %*").as_deref(),
+            Some("\\\\server\\share\\tool.py")
+        );
+        assert_eq!(quoted_path("\"relative\\app.exe\" %*"), None);
+        assert_eq!(quoted_path("C:\\apps\\app.exe %*"), None);
+        assert_eq!(quoted_path(""), None);
+    }
+}
@@ -0,0 +1,381 @@
+use colored::Colorize;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+
+use crate::installer::{Config, Scope};
+use crate::path_env::{self, EnvStore};
+use crate::report;
+
+/// Prints the user variables, marking the ones tracked in the config
+pub fn list(store: &dyn EnvStore, config: &Config) -> io::Result<()> {
+    let mut names = store.names()?;
+    names.sort_by_key(|name| name.to_lowercase());
+
+    let mut vars = Vec::new();
+    for name in names {
+        let value = store.get(&name)?.unwrap_or_default();
+        vars.push(json!({
+            "name": name,
+            "value": value,
+            "tracked": tracked(config, &name).map(|(_, tracked)| tracked),
+        }));
+        let flag = match tracked(config, &name) {
+            Some((_, tracked)) if *tracked == value => " [tracked]".blue().to_string(),
+            Some(_) => " [tracked, differs]".yellow().to_string(),
+            None => String::new(),
+        };
+        say!("{} = {}{}", name.bold(), value, flag);
+    }
+
+    report::emit(&vars);
+    Ok(())
+}
+
+pub fn get(store: &dyn EnvStore, name: &str) -> io::Result<()> {
+    match store.get(name)? {
+        Some(value) => {
+            if report::is_json() {
+                report::emit(&json!({ "name": name, "value": value }));
+            } else {
+                println!("{}", value);
+            }
+            Ok(())
+        }
+        None => Err(not_set(name)),
+    }
+}
+
+/// Sets the variable and tracks it in the config
+pub fn set(
+    store: &mut dyn EnvStore,
+    config: &mut Config,
+    name: &str,
+    value: &str,
+) -> io::Result<()> {
+    check_name(name)?;
+    store.set(name, value)?;
+
+    config
+        .env
+        .retain(|tracked, _| !tracked.eq_ignore_ascii_case(name));
+    config.env.insert(name.to_string(), value.to_string());
+    config.write()?;
+
+    say!("{} {} = {}", "Set".green(), name.bold(), value);
+    Ok(())
+}
+
+/// Removes the variable and stops tracking it
+pub fn unset(store: &mut dyn EnvStore, config: &mut Config, name: &str) -> io::Result<()> {
+    check_name(name)?;
+    let was_set = store.get(name)?.is_some();
+    let was_tracked = tracked(config, name).is_some();
+    if !was_set && !was_tracked {
+        return Err(not_set(name));
+    }
+
+    store.unset(name)?;
+    config
+        .env
+        .retain(|tracked, _| !tracked.eq_ignore_ascii_case(name));
+    config.write()?;
+
+    say!("{} {}", "Unset".red(), name.bold());
+    Ok(())
+}
+
+/// Prints the tracked variables as json, to be used with `rhz env apply <file>`
+pub fn export(config: &Config) -> io::Result<()> {
+    let sorted: BTreeMap<&String, &String> = config.env.iter().collect();
+    if report::is_json() {
+        report::emit(&sorted);
+    } else {
+        println!("{}", serde_json::to_string_pretty(&sorted)?);
+    }
+    Ok(())
+}
+
+/// Prints how the environment differs from the tracked variables
+pub fn diff(store: &dyn EnvStore, config: &Config) -> io::Result<()> {
+    let changes = changes(store, config)?;
+    report::emit(
+        &changes
+            .iter()
+            .map(|(name, current, tracked)| {
+                json!({ "name": name, "current": current, "tracked": tracked })
+            })
+            .collect::<Vec<_>>(),
+    );
+    if changes.is_empty() {
+        say!("{}", "The environment matches the config".green());
+        return Ok(());
+    }
+
+    for (name, current, tracked) in changes {
+        match current {
+            Some(current) => say!(
+                "{} {}: {} -> {}",
+                "~".yellow(),
+                name.bold(),
+                current.red(),
+                tracked.green()
+            ),
+            None => say!("{} {} = {}", "+".green(), name.bold(), tracked.green()),
+        }
+    }
+    Ok(())
+}
+
+/// Sets every tracked variable that differs, after merging in the ones from `file`
+pub fn apply(store: &mut dyn EnvStore, config: &mut Config, file: Option<&str>) -> io::Result<()> {
+    if let Some(file) = file {
+        let imported: HashMap<String, String> = serde_json::from_str(&fs::read_to_string(file)?)?;
+        for (name, value) in imported {
+            check_name(&name)?;
+            config
+                .env
+                .retain(|tracked, _| !tracked.eq_ignore_ascii_case(&name));
+            config.env.insert(name, value);
+        }
+        config.write()?;
+    }
+
+    let changes = changes(store, config)?;
+    if changes.is_empty() {
+        say!("{}", "The environment matches the config".green());
+        return Ok(());
+    }
+    for (name, _, tracked) in changes {
+        store.set(&name, &tracked)?;
+        say!("{} {} = {}", "Set".green(), name.bold(), tracked);
+    }
+    Ok(())
+}
+
+/// Lists the snapshots of a variable taken before rhiza changed it, newest first
+pub fn history(store: &dyn EnvStore, name: &str) -> io::Result<()> {
+    check_name(name)?;
+    let current = store.get(name)?;
+    let ids = path_env::snapshots(Scope::User, name)?;
+    if ids.is_empty() {
+        report::emit(&ids);
+        say!("{}", format!("No snapshots of {} yet", name).yellow());
+        return Ok(());
+    }
+
+    let mut history = Vec::new();
+    for id in ids {
+        let value = path_env::read_snapshot(Scope::User, name, &id)?;
+        let taken = path_env::snapshot_time(&id);
+        let flag = if current.as_ref() == Some(&value) {
+            " [current]".blue().to_string()
+        } else {
+            String::new()
+        };
+        say!("{} {} {}{}", id.bold(), taken, value, flag);
+        history.push(json!({ "id": id, "taken": taken, "value": value }));
+    }
+    report::emit(&history);
+    Ok(())
+}
+
+/// Sets a variable back to a snapshot once confirmed, and tracks that value if it was tracked
+pub fn restore(
+    store: &mut dyn EnvStore,
+    config: &mut Config,
+    name: &str,
+    id: &str,
+    confirm: &dyn Fn(&str) -> bool,
+) -> io::Result<()> {
+    check_name(name)?;
+    let value = path_env::read_snapshot(Scope::User, name, id)?;
+    let current = store.get(name)?;
+    if current.as_ref() == Some(&value) {
+        say!(
+            "{}",
+            format!("{} already matches this snapshot", name).yellow()
+        );
+        return Ok(());
+    }
+
+    say!(
+        "{}: {} -> {}",
+        name.bold(),
+        current.as_deref().unwrap_or("(unset)").red(),
+        value.green()
+    );
+    if !confirm(&format!("Restore {} from {}?", name, id)) {
+        return Ok(());
+    }
+    store.set(name, &value)?;
+    if let Some((tracked, _)) = tracked(config, name) {
+        config.env.insert(tracked.to_string(), value);
+        config.write()?;
+    }
+    say!("{}", format!("Restored {} from {}", name, id).green());
+    Ok(())
+}
+
+/// Tracked variables whose value differs, with their current value if they're set
+fn changes(
+    store: &dyn EnvStore,
+    config: &Config,
+) -> io::Result<Vec<(String, Option<String>, String)>> {
+    let mut names: Vec<&String> = config.env.keys().collect();
+    names.sort_by_key(|name| name.to_lowercase());
+
+    let mut changes = Vec::new();
+    for name in names {
+        let tracked = &config.env[name];
+        let current = store.get(name)?;
+        if current.as_ref() != Some(tracked) {
+            changes.push((name.to_string(), current, tracked.to_string()));
+        }
+    }
+    Ok(changes)
+}
+
+// Variable names are case-insensitive on Windows
+fn tracked<'a>(config: &'a Config, name: &str) -> Option<(&'a String, &'a String)> {
+    config
+        .env
+        .iter()
+        .find(|(tracked, _)| tracked.eq_ignore_ascii_case(name))
+}
+
+fn check_name(name: &str) -> io::Result<()> {
+    if name.eq_ignore_ascii_case("Path") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Use 'rhz path' to change the PATH",
+        ));
+    }
+    if name.is_empty() || name.contains('=') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("'{}' isn't a valid variable name", name),
+        ));
+    }
+    Ok(())
+}
+
+fn not_set(name: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("'{}' is not set", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::path_env::tests::MemoryEnv;
+    use crate::paths;
+
+    fn setup() -> (MemoryEnv, Config) {
+        // set and unset save the config
+        fs::create_dir_all(paths::home()).unwrap();
+        (MemoryEnv::default(), Config::default())
+    }
+
+    #[test]
+    fn set_writes_and_tracks() {
+        let (mut store, mut config) = setup();
+        set(&mut store, &mut config, "java_home", "C:\\jdk17").unwrap();
+        set(&mut store, &mut config, "JAVA_HOME", "C:\\jdk21").unwrap();
+
+        assert_eq!(
+            store.get("JAVA_HOME").unwrap().as_deref(),
+            Some("C:\\jdk21")
+        );
+        // Tracked once, under the latest spelling
+        assert_eq!(config.env.len(), 1);
+        assert_eq!(config.env["JAVA_HOME"], "C:\\jdk21");
+    }
+
+    #[test]
+    fn restores_snapshots() {
+        let (mut store, mut config) = setup();
+        let name = "RHIZA_TEST_RESTORE";
+        path_env::snapshot(Scope::User, name, "C:\\jdk17").unwrap();
+        let id = path_env::snapshots(Scope::User, name).unwrap().remove(0);
+        set(&mut store, &mut config, name, "C:\\jdk21").unwrap();
+        history(&store, name).unwrap();
+
+        // Nothing changes until it's confirmed
+        restore(&mut store, &mut config, name, &id, &|_| false).unwrap();
+        assert_eq!(store.get(name).unwrap().as_deref(), Some("C:\\jdk21"));
+
+        restore(&mut store, &mut config, name, &id, &|_| true).unwrap();
+        assert_eq!(store.get(name).unwrap().as_deref(), Some("C:\\jdk17"));
+        assert_eq!(config.env[name], "C:\\jdk17");
+
+        let err = restore(&mut store, &mut config, name, "latest", &|_| true).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = restore(&mut store, &mut config, name, "20000101-000000", &|_| true).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        let err = restore(&mut store, &mut config, "Path", &id, &|_| true).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn set_rejects_invalid_names() {
+        let (mut store, mut config) = setup();
+        for name in ["Path", "PATH", "", "A=B"] {
+            let err = set(&mut store, &mut config, name, "value").unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+        assert!(store.0.is_empty() && config.env.is_empty());
+    }
+
+    #[test]
+    fn get_reads_the_store() {
+        let (mut store, _) = setup();
+        store.set("EDITOR", "hx").unwrap();
+        assert!(get(&store, "EDITOR").is_ok());
+        let err = get(&store, "VISUAL").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn unset_removes_and_untracks() {
+        let (mut store, mut config) = setup();
+        set(&mut store, &mut config, "EDITOR", "hx").unwrap();
+        unset(&mut store, &mut config, "editor").unwrap();
+        assert!(store.get("EDITOR").unwrap().is_none());
+        assert!(config.env.is_empty());
+
+        let err = unset(&mut store, &mut config, "EDITOR").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn unset_untracks_variables_removed_elsewhere() {
+        let (mut store, mut config) = setup();
+        set(&mut store, &mut config, "EDITOR", "hx").unwrap();
+        store.unset("EDITOR").unwrap();
+        unset(&mut store, &mut config, "EDITOR").unwrap();
+        assert!(config.env.is_empty());
+    }
+
+    #[test]
+    fn apply_sets_what_differs() {
+        let (mut store, mut config) = setup();
+        store.set("EDITOR", "notepad").unwrap();
+        store.set("PAGER", "less").unwrap();
+        config.env.insert("EDITOR".to_string(), "hx".to_string());
+        config.env.insert("PAGER".to_string(), "less".to_string());
+        config.env.insert("SHELL".to_string(), "nu".to_string());
+
+        let names: Vec<String> = changes(&store, &config)
+            .unwrap()
+            .into_iter()
+            .map(|(name, _, _)| name)
+            .collect();
+        assert_eq!(names, ["EDITOR", "SHELL"]);
+
+        apply(&mut store, &mut config, None).unwrap();
+        assert_eq!(store.get("EDITOR").unwrap().as_deref(), Some("hx"));
+        assert_eq!(store.get("SHELL").unwrap().as_deref(), Some("nu"));
+        assert!(changes(&store, &config).unwrap().is_empty());
+    }
+}
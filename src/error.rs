@@ -0,0 +1,107 @@
+use inquire::InquireError;
+use std::fmt;
+use std::io;
+
+/// Everything a command can fail with, each kind exiting with its own code
+#[derive(Debug)]
+pub enum Error {
+    /// config.json can't be read or parsed
+    Config(String),
+    /// Reading or writing the environment in the registry failed
+    Registry(io::Error),
+    Io(io::Error),
+    /// Esc or Ctrl+C at a prompt
+    Cancelled,
+    NotFound(String),
+    /// A file rhiza doesn't know how to launch
+    UnsupportedTarget(String),
+    /// A pre-hook failed, with its name and how
+    Hook(&'static str, String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// The exit code of rhz when a command fails with this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Io(_) => 1,
+            // 2 is taken by clap for invalid arguments
+            Error::Config(_) => 3,
+            Error::Registry(_) => 4,
+            Error::NotFound(_) => 5,
+            Error::UnsupportedTarget(_) => 6,
+            Error::Hook(..) => 7,
+            Error::Cancelled => 130,
+        }
+    }
+
+    /// A registry failure for code returning `io::Result`, it's turned back into
+    /// `Error::Registry` at the top
+    pub fn registry(err: io::Error) -> io::Error {
+        Error::Registry(err).into()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Config(msg) => write!(f, "Invalid config.json: {}", msg),
+            Error::Registry(err) => write!(f, "Couldn't access the registry: {}", err),
+            Error::Io(err) => write!(f, "{}", err),
+            Error::Cancelled => write!(f, "Cancelled"),
+            Error::NotFound(msg) => write!(f, "{}", msg),
+            Error::UnsupportedTarget(target) => {
+                write!(f, "Unsupported target {}, see 'rhz add --help'", target)
+            }
+            Error::Hook(hook, failure) => write!(f, "The {} hook {}, aborting", hook, failure),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        // Typed errors raised under `io::Result` travel wrapped in an io::Error
+        match err.downcast::<Error>() {
+            Ok(err) => err,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Error::NotFound(err.to_string()),
+            Err(err) => Error::Io(err),
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        let kind = match &err {
+            Error::Io(inner) | Error::Registry(inner) => inner.kind(),
+            Error::NotFound(_) => io::ErrorKind::NotFound,
+            Error::Cancelled => io::ErrorKind::Interrupted,
+            Error::Config(_) | Error::UnsupportedTarget(_) => io::ErrorKind::InvalidData,
+            Error::Hook(..) => io::ErrorKind::Other,
+        };
+        match err {
+            Error::Io(inner) => inner,
+            err => io::Error::new(kind, err),
+        }
+    }
+}
+
+impl From<InquireError> for Error {
+    fn from(err: InquireError) -> Self {
+        match err {
+            InquireError::OperationCanceled | InquireError::OperationInterrupted => {
+                Error::Cancelled
+            }
+            InquireError::IO(err) => err.into(),
+            err => Error::Io(io::Error::other(err.to_string())),
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Config(err.to_string())
+    }
+}
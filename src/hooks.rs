@@ -0,0 +1,191 @@
+use colored::Colorize;
+use serde_derive::{Deserialize, Serialize};
+use std::io;
+use std::process::{self, Stdio};
+
+use crate::error::Error;
+use crate::installer::{Config, Entry, Scope};
+use crate::paths;
+use crate::report;
+
+/// Shell commands run when rhiza changes things, e.g to refresh a launcher's index
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Hooks {
+    /// Before `rhz run`, failing aborts it
+    pub pre_run: Option<String>,
+    pub post_run: Option<String>,
+    /// After a command is added, by `add`, `url`, `lnk`, `path --file` or `crawl`
+    pub post_add: Option<String>,
+    /// After a command is unlinked, by `rm` or by `profile use` dropping it
+    pub post_rm: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Hook {
+    PreRun,
+    PostRun,
+    PostAdd,
+    PostRm,
+}
+
+impl Hook {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Hook::PreRun => "pre-run",
+            Hook::PostRun => "post-run",
+            Hook::PostAdd => "post-add",
+            Hook::PostRm => "post-rm",
+        }
+    }
+
+    fn action(&self) -> &'static str {
+        match self {
+            Hook::PreRun | Hook::PostRun => "run",
+            Hook::PostAdd => "add",
+            Hook::PostRm => "rm",
+        }
+    }
+
+    fn command<'a>(&self, hooks: &'a Hooks) -> Option<&'a str> {
+        match self {
+            Hook::PreRun => hooks.pre_run.as_deref(),
+            Hook::PostRun => hooks.post_run.as_deref(),
+            Hook::PostAdd => hooks.post_add.as_deref(),
+            Hook::PostRm => hooks.post_rm.as_deref(),
+        }
+    }
+}
+
+/// Runs `hook` if it's set, with `RHIZA_HOOK`, `RHIZA_ACTION`, `RHIZA_SCOPE` and `RHIZA_HOME`
+/// plus `vars` in its environment. A failing pre-hook is an error, a failing post-hook a
+/// warning since the change is already done
+pub fn run(config: &Config, hook: Hook, scope: Scope, vars: &[(&str, String)]) -> io::Result<()> {
+    let Some(command) = hook.command(&config.hooks) else {
+        return Ok(());
+    };
+
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    // With --json, stdout only carries rhiza's result
+    let stdout = if report::is_json() {
+        Stdio::from(io::stderr())
+    } else {
+        Stdio::inherit()
+    };
+    let status = process::Command::new(shell)
+        .arg(flag)
+        .arg(command)
+        .current_dir(paths::home())
+        .env("RHIZA_HOOK", hook.name())
+        .env("RHIZA_ACTION", hook.action())
+        .env("RHIZA_SCOPE", scope.name())
+        .env("RHIZA_HOME", paths::home())
+        .envs(vars.iter().map(|(name, value)| (name, value)))
+        .stdout(stdout)
+        .status();
+
+    let failure = match status {
+        Ok(status) if status.success() => return Ok(()),
+        Ok(status) => match status.code() {
+            Some(code) => format!("exited with {}", code),
+            None => "was killed".to_string(),
+        },
+        Err(err) => format!("couldn't start: {}", err),
+    };
+    match hook {
+        Hook::PreRun => Err(Error::Hook(hook.name(), failure).into()),
+        _ => {
+            let msg = format!("The {} hook {}", hook.name(), failure);
+            say!("{}", msg.yellow());
+            Ok(())
+        }
+    }
+}
+
+/// The variables describing a single command, for `post-add` and `post-rm`
+pub fn command_vars(key: &str, entry: &Entry) -> Vec<(&'static str, String)> {
+    vec![
+        ("RHIZA_KEY", key.to_string()),
+        ("RHIZA_TARGET", entry.target.to_string()),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn config(hooks: Hooks) -> Config {
+        // Hooks run from the rhiza home
+        fs::create_dir_all(paths::home()).unwrap();
+        Config {
+            hooks,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn hooks_get_their_variables() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("env.txt");
+        let dump = if cfg!(windows) { "set" } else { "env" };
+        let config = config(Hooks {
+            post_add: Some(format!("{} > \"{}\"", dump, out.display())),
+            ..Hooks::default()
+        });
+        let entry = Entry::new("C:\\tools\\app.exe".to_string());
+
+        run(
+            &config,
+            Hook::PostAdd,
+            Scope::Machine,
+            &command_vars("app", &entry),
+        )
+        .unwrap();
+        let env = fs::read_to_string(&out).unwrap();
+        let home = format!("RHIZA_HOME={}", paths::home().display());
+        for var in [
+            "RHIZA_HOOK=post-add",
+            "RHIZA_ACTION=add",
+            "RHIZA_SCOPE=machine",
+            "RHIZA_KEY=app",
+            "RHIZA_TARGET=C:\\tools\\app.exe",
+            &home,
+        ] {
+            assert!(env.lines().any(|line| line.trim() == var), "{}", var);
+        }
+    }
+
+    #[test]
+    fn failing_pre_hooks_abort() {
+        let config = config(Hooks {
+            pre_run: Some("exit 3".to_string()),
+            ..Hooks::default()
+        });
+        let err = run(&config, Hook::PreRun, Scope::User, &[]).unwrap_err();
+        match Error::from(err) {
+            Error::Hook(hook, failure) => {
+                assert_eq!(hook, "pre-run");
+                assert_eq!(failure, "exited with 3");
+            }
+            err => panic!("expected a hook error, got {}", err),
+        }
+    }
+
+    #[test]
+    fn failing_post_hooks_only_warn() {
+        let config = config(Hooks {
+            post_run: Some("exit 3".to_string()),
+            post_rm: Some("exit 1".to_string()),
+            ..Hooks::default()
+        });
+        run(&config, Hook::PostRun, Scope::User, &[]).unwrap();
+        run(&config, Hook::PostRm, Scope::User, &[]).unwrap();
+        // Unset hooks do nothing
+        run(&config, Hook::PostAdd, Scope::User, &[]).unwrap();
+    }
+}
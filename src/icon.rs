@@ -0,0 +1,139 @@
+use lnk_parser::LNKParser;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::installer::{self, Scope};
+use crate::path_env;
+use crate::pe;
+use crate::url_file::UrlFile;
+
+/// Where the icon of `key` is cached
+pub fn cache_path(key: &str, scope: Scope) -> PathBuf {
+    Path::new(&scope.root())
+        .join("icons")
+        .join(format!("{}.ico", key))
+}
+
+/// Extracts the icon of `source` into the icons folder of `scope`, so links don't depend on
+/// files that may move or vanish. Returns `None` if `source` has no icon to take
+pub fn cache(key: &str, source: &Path, scope: Scope) -> io::Result<Option<PathBuf>> {
+    let icon = match find(source)? {
+        Some(icon) => icon,
+        None => return Ok(None),
+    };
+
+    let path = cache_path(key, scope);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::read(&path).ok().as_ref() != Some(&icon) {
+        fs::write(&path, icon)?;
+    }
+    Ok(Some(path))
+}
+
+/// The .ico bytes of an executable, or of the icon a .url or .lnk points to
+pub fn find(source: &Path) -> io::Result<Option<Vec<u8>>> {
+    let extension = source
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "exe" | "dll" | "ico" => load(source, 0),
+        "url" => {
+            let url_file = UrlFile::read(source)?;
+            match url_file.icon_file() {
+                Some(file) => load(
+                    Path::new(&path_env::expand_vars(file)),
+                    url_file.icon_index(),
+                ),
+                None => Ok(None),
+            }
+        }
+        "lnk" => {
+            let source = source.to_string_lossy();
+            let link = LNKParser::from_path(&source)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+            match link.get_icon_location() {
+                Some(location) => load(
+                    Path::new(&path_env::expand_vars(&location.string)),
+                    link.get_shell_link_header().icon_index as i32,
+                ),
+                // Without an icon location Windows shows the target's icon
+                None => match installer::read_shortcut(&source) {
+                    Some(target) => load(Path::new(&target), 0),
+                    None => Ok(None),
+                },
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Reads an .ico file, or the icon group `index` of an executable
+fn load(file: &Path, index: i32) -> io::Result<Option<Vec<u8>>> {
+    if !file.is_file() {
+        return Ok(None);
+    }
+    let bytes = fs::read(file)?;
+    if bytes.starts_with(b"MZ") {
+        pe::icon(&bytes, index)
+    } else if bytes.starts_with(&[0, 0, 1, 0]) {
+        Ok(Some(bytes))
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lnk::ShellLink;
+    use crate::pe::tests::{ico, sample, IMAGE_1, IMAGE_2};
+
+    #[test]
+    fn finds_the_icons_of_executables_and_links() {
+        let dir = tempfile::tempdir().unwrap();
+        let exe = dir.path().join("app.exe");
+        fs::write(&exe, sample()).unwrap();
+        let both = Some(ico(&[IMAGE_1, IMAGE_2]));
+        let second = Some(ico(&[IMAGE_2]));
+
+        assert_eq!(find(&exe).unwrap(), both);
+
+        let ico_file = dir.path().join("app.ico");
+        fs::write(&ico_file, ico(&[IMAGE_2])).unwrap();
+        assert_eq!(find(&ico_file).unwrap(), second);
+
+        let url = dir.path().join("site.url");
+        let mut url_file = UrlFile::new("https://example.com");
+        url_file.set_icon(&exe.to_string_lossy(), -7);
+        url_file.write(&url).unwrap();
+        assert_eq!(find(&url).unwrap(), second);
+
+        let lnk = dir.path().join("app.lnk");
+        let mut link = ShellLink::new(&exe);
+        link.write(&lnk).unwrap();
+        // Without an icon location, the target's icon
+        assert_eq!(find(&lnk).unwrap(), both);
+        link.icon_location = Some(exe.to_string_lossy().to_string());
+        link.icon_index = 1;
+        link.write(&lnk).unwrap();
+        assert_eq!(find(&lnk).unwrap(), second);
+    }
+
+    #[test]
+    fn missing_icons_are_none() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = dir.path().join("site.url");
+        UrlFile::new("https://example.com").write(&url).unwrap();
+        assert_eq!(find(&url).unwrap(), None);
+
+        let text = dir.path().join("notes.txt");
+        fs::write(&text, "hello").unwrap();
+        assert_eq!(find(&text).unwrap(), None);
+        assert_eq!(find(&dir.path().join("gone.exe")).unwrap(), None);
+    }
+}
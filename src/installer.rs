@@ -1,26 +1,356 @@
 use colored::Colorize;
 use lnk_parser::LNKParser;
+use serde::Deserializer;
 use serde_derive::{Deserialize, Serialize};
 use shellexpand::tilde;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::io;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+#[cfg(windows)]
 use winreg::enums::*;
-use winreg::RegKey; // Add this import
-
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[cfg(windows)]
+use winreg::{RegKey, RegValue};
+
+use crate::error::Error;
+use crate::hooks::Hooks;
+use crate::path_env::{self, RegistryEnv};
+use crate::paths;
+use crate::pe::{self, Subsystem};
+use crate::start_menu;
+use crate::target::TargetKind;
+use crate::url_file::UrlFile;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Config {
-    pub commands: std::collections::HashMap<String, String>,
+    #[serde(default, deserialize_with = "deserialize_commands")]
+    pub commands: HashMap<String, Entry>,
+    #[serde(default)]
     pub skipped: Vec<String>,
+    /// Directories added to the PATH with `rhz path`
+    #[serde(default)]
+    pub paths: Vec<String>,
+    #[serde(default)]
+    pub path_limits: PathLimits,
+    /// User environment variables managed with `rhz env`
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Name of rhiza's folder in the Start Menu, `rhiza` by default
+    #[serde(default)]
+    pub start_menu_folder: Option<String>,
+    /// Command sets besides the default one in `commands`, see `rhz profile`
+    #[serde(default)]
+    pub profiles: HashMap<String, Profile>,
+    /// The profiles `rhz run` applies, new commands go to the first one.
+    /// Just the default profile when empty
+    #[serde(default)]
+    pub active: Vec<String>,
+    #[serde(default)]
+    pub hooks: Hooks,
+    /// The commands of the profiles that aren't active, filled in by `applied`
+    #[serde(skip)]
+    pub inactive: HashSet<String>,
+}
+
+/// The profile stored in `Config::commands`
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// A named set of commands, e.g `work` or `gaming`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profile {
+    #[serde(default, deserialize_with = "deserialize_commands")]
+    pub commands: HashMap<String, Entry>,
+}
+
+/// Thresholds for the expanded length of the PATH, in characters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PathLimits {
+    /// Past this, setx and older installers truncate the PATH
+    pub warn: usize,
+    /// Past this, changes that make the PATH longer are refused
+    pub max: usize,
+}
+
+impl Default for PathLimits {
+    fn default() -> Self {
+        PathLimits {
+            warn: 2047,
+            max: 32767,
+        }
+    }
+}
+
+/// How a command's shim starts its target
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LaunchMode {
+    /// Detached for GUI apps and attached for console apps, based on the target
+    #[default]
+    Auto,
+    /// Start the target and return right away
+    Detached,
+    /// Wait for the target, forwarding stdio and its exit code
+    Attached,
+}
+
+impl LaunchMode {
+    pub fn parse(value: &str) -> Option<LaunchMode> {
+        match value.to_lowercase().as_str() {
+            "auto" => Some(LaunchMode::Auto),
+            "detached" | "gui" => Some(LaunchMode::Detached),
+            "attached" | "console" => Some(LaunchMode::Attached),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LaunchMode::Auto => "auto",
+            LaunchMode::Detached => "detached",
+            LaunchMode::Attached => "attached",
+        }
+    }
+
+    /// Resolves `Auto` to the mode that fits the target
+    pub fn resolve(&self, target: &str) -> LaunchMode {
+        if *self != LaunchMode::Auto {
+            return *self;
+        }
+
+        // Shortcuts launch whatever they point to
+        let resolved = read_shortcut(target).unwrap_or_else(|| target.to_string());
+        let path = Path::new(&resolved);
+        let attached = match TargetKind::detect(path) {
+            Some(TargetKind::Exe) => pe::subsystem(path).is_ok_and(|s| s == Subsystem::Console),
+            Some(TargetKind::Batch | TargetKind::PowerShell | TargetKind::Jar) => true,
+            Some(TargetKind::Python) => !resolved.to_lowercase().ends_with(".pyw"),
+            _ => false,
+        };
+
+        if attached {
+            LaunchMode::Attached
+        } else {
+            LaunchMode::Detached
+        }
+    }
+}
+
+/// Who a command is installed for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// The rhiza home (`~\.rhiza` by default), the user's Start Menu and `HKCU\Environment`
+    #[default]
+    User,
+    /// `C:\ProgramData\rhiza`, the all-users Start Menu and the system environment
+    Machine,
+}
+
+impl Scope {
+    pub fn parse(value: &str) -> Option<Scope> {
+        match value.to_lowercase().as_str() {
+            "user" => Some(Scope::User),
+            "machine" | "system" => Some(Scope::Machine),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scope::User => "user",
+            Scope::Machine => "machine",
+        }
+    }
+
+    /// Holds the `src` and `bin` directories
+    pub fn root(&self) -> String {
+        match self {
+            Scope::User => paths::home().to_string_lossy().to_string(),
+            Scope::Machine => format!("{}\\rhiza", program_data()),
+        }
+    }
+
+    pub fn src_dir(&self) -> String {
+        self.root() + "\\src"
+    }
+
+    pub fn bin_dir(&self) -> String {
+        self.root() + "\\bin"
+    }
+
+    /// The Start Menu `Programs` folder, rhiza's folder goes in there
+    pub fn start_menu_programs(&self) -> String {
+        match self {
+            Scope::User => format!(
+                "{}\\AppData\\Roaming\\Microsoft\\Windows\\Start Menu\\Programs",
+                tilde("~")
+            ),
+            Scope::Machine => format!(
+                "{}\\Microsoft\\Windows\\Start Menu\\Programs",
+                program_data()
+            ),
+        }
+    }
+
+    pub fn desktop(&self) -> String {
+        match self {
+            Scope::User => tilde("~\\Desktop").to_string(),
+            Scope::Machine => format!(
+                "{}\\Desktop",
+                std::env::var("PUBLIC").unwrap_or_else(|_| "C:\\Users\\Public".to_string())
+            ),
+        }
+    }
+
+    pub fn startup(&self) -> String {
+        self.start_menu_programs() + "\\Startup"
+    }
+
+    /// Fails with a clear message when writing to this scope needs an elevated shell
+    pub fn check_elevation(&self) -> io::Result<()> {
+        if *self == Scope::Machine && !is_elevated() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "--scope machine needs administrator rights, run rhz from an elevated shell",
+            ));
+        }
+        Ok(())
+    }
+
+    #[cfg(windows)]
+    fn environment_key(&self, flags: u32) -> io::Result<RegKey> {
+        let (hkey, path) = match self {
+            Scope::User => (HKEY_CURRENT_USER, "Environment"),
+            Scope::Machine => (
+                HKEY_LOCAL_MACHINE,
+                "SYSTEM\\CurrentControlSet\\Control\\Session Manager\\Environment",
+            ),
+        };
+        RegKey::predef(hkey)
+            .open_subkey_with_flags(path, flags)
+            .map_err(Error::registry)
+    }
+}
+
+fn program_data() -> String {
+    std::env::var("ProgramData").unwrap_or_else(|_| "C:\\ProgramData".to_string())
+}
+
+#[cfg(windows)]
+pub fn is_elevated() -> bool {
+    unsafe { winapi::um::shlobj::IsUserAnAdmin() != 0 }
+}
+
+#[cfg(not(windows))]
+pub fn is_elevated() -> bool {
+    false
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub target: String,
+    #[serde(default)]
+    pub launch: LaunchMode,
+    #[serde(default)]
+    pub scope: Scope,
+    /// Where `rhz run` puts the command
+    #[serde(default = "Output::defaults")]
+    pub outputs: Vec<Output>,
+    /// Start Menu subfolder, e.g `Dev` or `Games/Emulators`
+    #[serde(default)]
+    pub category: Option<String>,
+}
+
+impl Entry {
+    pub fn new(target: String) -> Self {
+        Entry {
+            target,
+            launch: LaunchMode::default(),
+            scope: Scope::default(),
+            outputs: Output::defaults(),
+            category: None,
+        }
+    }
+
+    pub fn has(&self, output: Output) -> bool {
+        self.outputs.contains(&output)
+    }
+
+    /// Only exposed on the PATH (or nowhere), so it doesn't need a link in `src`
+    pub fn is_shim_only(&self) -> bool {
+        self.outputs.iter().all(|output| *output == Output::Shim)
+    }
+}
+
+/// A place `rhz run` puts a command
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Output {
+    /// A launcher in the bin directory, on the PATH
+    Shim,
+    StartMenu,
+    Desktop,
+    /// The Startup folder, to launch at login
+    Startup,
+}
+
+impl Output {
+    pub const NAMES: [&'static str; 4] = ["shim", "start-menu", "desktop", "startup"];
+
+    pub fn defaults() -> Vec<Output> {
+        vec![Output::Shim, Output::StartMenu]
+    }
+
+    pub fn parse(value: &str) -> Option<Output> {
+        match value.to_lowercase().as_str() {
+            "shim" | "path" => Some(Output::Shim),
+            "start-menu" | "startmenu" => Some(Output::StartMenu),
+            "desktop" => Some(Output::Desktop),
+            "startup" => Some(Output::Startup),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Output::Shim => "shim",
+            Output::StartMenu => "start-menu",
+            Output::Desktop => "desktop",
+            Output::Startup => "startup",
+        }
+    }
+}
+
+// Commands used to be stored as `name: target`, accept both forms
+fn deserialize_commands<'de, D>(deserializer: D) -> Result<HashMap<String, Entry>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Target(String),
+        Entry(Entry),
+    }
+
+    let commands: HashMap<String, Repr> = serde::Deserialize::deserialize(deserializer)?;
+    Ok(commands
+        .into_iter()
+        .map(|(key, repr)| match repr {
+            Repr::Target(target) => (key, Entry::new(target)),
+            Repr::Entry(entry) => (key, entry),
+        })
+        .collect())
 }
 
 impl Config {
     pub fn expand(&self) -> Vec<String> {
         let mut res = Vec::new();
 
-        for (_key, path) in &self.commands {
-            if let Some(lnk_result) = read_shortcut(path) {
+        for entry in self.current().values() {
+            if let Some(lnk_result) = read_shortcut(&entry.target) {
                 res.push(lnk_result);
             }
         }
@@ -33,61 +363,137 @@ impl Config {
         res
     }
 
-    pub fn write(&self) -> Result<(), io::Error> {
-        let rhiza_dir = tilde("~/.rhiza").to_string();
-        let config_file = Path::new(&rhiza_dir).join("config.json");
-        let content = serde_json::to_string_pretty(&self)?;
-        fs::write(config_file, content)?;
+    /// The active profiles, in order
+    pub fn active_profiles(&self) -> Vec<String> {
+        if self.active.is_empty() {
+            vec![DEFAULT_PROFILE.to_string()]
+        } else {
+            self.active.clone()
+        }
+    }
 
-        Ok(())
+    /// Every profile name, the default one first
+    pub fn profile_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.profiles.keys().cloned().collect();
+        names.sort();
+        names.insert(0, DEFAULT_PROFILE.to_string());
+        names
     }
-}
 
-pub fn copy_src() -> std::io::Result<()> {
-    let user_dir = shellexpand::tilde("~").to_string();
-    let src = format!("{}\\.rhiza\\src", user_dir);
-    let src = Path::new(&src);
-    let target = format!(
-        "{}\\AppData\\Roaming\\Microsoft\\Windows\\Start Menu\\Programs\\rhiza",
-        user_dir
-    );
-    let target = Path::new(&target);
+    pub fn profile(&self, name: &str) -> Option<&HashMap<String, Entry>> {
+        if name == DEFAULT_PROFILE {
+            return Some(&self.commands);
+        }
+        self.profiles.get(name).map(|profile| &profile.commands)
+    }
 
-    if target.exists() {
-        fs::remove_dir_all(target)?;
+    /// The commands of the profile that new commands go to
+    pub fn current(&self) -> &HashMap<String, Entry> {
+        let name = &self.active_profiles()[0];
+        self.profile(name).unwrap_or(&self.commands)
     }
-    fs::create_dir_all(target)?;
 
-    for entry in fs::read_dir(src)? {
-        let entry = entry?;
-        let src_path = entry.path();
-        let dest_path = target.join(entry.file_name());
+    pub fn profile_mut(&mut self, name: &str) -> Option<&mut HashMap<String, Entry>> {
+        if name == DEFAULT_PROFILE {
+            return Some(&mut self.commands);
+        }
+        self.profiles
+            .get_mut(name)
+            .map(|profile| &mut profile.commands)
+    }
 
-        fs::copy(&src_path, &dest_path)?;
+    pub fn current_mut(&mut self) -> &mut HashMap<String, Entry> {
+        let name = self.active_profiles().remove(0);
+        match self.profiles.get_mut(&name) {
+            Some(profile) => &mut profile.commands,
+            None => &mut self.commands,
+        }
     }
 
-    Ok(())
+    /// An applied command, to edit it in the profile it comes from
+    pub fn command_mut(&mut self, key: &str) -> Option<&mut Entry> {
+        let (profile, _) = self.applied_commands().remove(key)?;
+        self.profile_mut(&profile)?.get_mut(key)
+    }
+
+    /// Removes an applied command from the profile it comes from
+    pub fn remove_command(&mut self, key: &str) -> Option<Entry> {
+        let (profile, _) = self.applied_commands().remove(key)?;
+        self.profile_mut(&profile)?.remove(key)
+    }
+
+    /// The commands of the active profiles with the profile each comes from,
+    /// the first profile defining a name wins
+    pub fn applied_commands(&self) -> HashMap<String, (String, Entry)> {
+        let mut applied = HashMap::new();
+        for name in self.active_profiles() {
+            for (key, entry) in self.profile(&name).into_iter().flatten() {
+                applied
+                    .entry(key.to_string())
+                    .or_insert_with(|| (name.to_string(), entry.clone()));
+            }
+        }
+        applied
+    }
+
+    /// A copy whose `commands` are the ones `rhz run` applies
+    pub fn applied(&self) -> Config {
+        let mut config = self.clone();
+        config.commands = self
+            .applied_commands()
+            .into_iter()
+            .map(|(key, (_, entry))| (key, entry))
+            .collect();
+        config.inactive = self
+            .profile_names()
+            .iter()
+            .filter_map(|name| self.profile(name))
+            .flat_map(|commands| commands.keys())
+            .filter(|key| !config.commands.contains_key(*key))
+            .cloned()
+            .collect();
+        config
+    }
+
+    /// Whether `key` only belongs to profiles that aren't active, so nothing should link it
+    pub fn is_inactive(&self, key: &str) -> bool {
+        !self.commands.contains_key(key) && self.inactive.contains(key)
+    }
+
+    /// rhiza's folder in the Start Menu of `scope`, failing when `start_menu_folder` would
+    /// point anywhere but a folder of its own
+    pub fn start_menu_root(&self, scope: Scope) -> io::Result<PathBuf> {
+        let folder = match &self.start_menu_folder {
+            Some(folder) => start_menu::folder_name(folder)?,
+            None => "rhiza".to_string(),
+        };
+        Ok(Path::new(&scope.start_menu_programs()).join(folder))
+    }
+
+    pub fn write(&self) -> Result<(), io::Error> {
+        let content = serde_json::to_string_pretty(&self)?;
+        fs::write(paths::config_file(), content)?;
+
+        Ok(())
+    }
 }
 
 pub fn check() -> io::Result<Config> {
-    // Check if the .rhiza directory exists
-    let rhiza_dir = tilde("~\\.rhiza").to_string();
-    let config_file = Path::new(&rhiza_dir).join("config.json");
+    let rhiza_dir = paths::home();
+    let config_file = paths::config_file();
 
     let mut needs_setup = false;
 
-    // Check if the .rhiza directory exists
-    if !Path::new(&rhiza_dir).exists() {
-        println!(
-            "{}",
-            ".rhiza directory does not exist, running setup...".yellow()
-        );
+    // Check if the rhiza home exists
+    if !rhiza_dir.exists() {
+        let msg = format!("{} does not exist, running setup...", rhiza_dir.display());
+        say!("{}", msg.yellow());
         needs_setup = true;
     }
 
     // Check if the config.json file exists
     if !config_file.exists() {
-        println!(
+        say!(
             "{}",
             "config.json does not exist, running setup...".yellow()
         );
@@ -95,82 +501,184 @@ pub fn check() -> io::Result<Config> {
     }
 
     // Check if the new path is already in the PATH
-    let hkcu = RegKey::predef(HKEY_CURRENT_USER);
-    let environment_key = hkcu.open_subkey_with_flags("Environment", KEY_READ | KEY_WRITE)?;
-    let current_path: String = environment_key.get_value("Path")?;
+    let current_path = read_path(Scope::User)?;
 
-    let bin_dir = rhiza_dir + "\\bin";
-    if !current_path.split(';').any(|path| path == bin_dir) {
+    let bin_dir = Scope::User.bin_dir();
+    if !path_env::split(&current_path)
+        .iter()
+        .any(|path| path_env::normalize(path) == path_env::normalize(&bin_dir))
+    {
         needs_setup = true;
     }
 
     if needs_setup {
         setup_rhiza_config()?;
         add_to_path_permanently(&bin_dir)?;
-        return check();
+        check()
     } else {
-        let config_contents = fs::read_to_string(&config_file)?;
-        let config: Config = serde_json::from_str(&config_contents).unwrap_or_default();
-        Ok(config)
+        read_config()
     }
 }
 
-pub fn add_to_path_permanently(new_path: &str) -> io::Result<()> {
-    // Open the environment variables key in the registry for the current user
-    let hkcu = RegKey::predef(HKEY_CURRENT_USER);
-    let environment_key = hkcu.open_subkey_with_flags("Environment", KEY_READ | KEY_WRITE)?;
+/// Reads the config without running the setup `check` does
+pub fn read_config() -> io::Result<Config> {
+    read_config_from(&paths::config_file())
+}
 
-    // Get the current PATH value
-    let current_path: String = environment_key.get_value("Path")?;
+pub fn read_config_from(file: &Path) -> io::Result<Config> {
+    let config_contents = fs::read_to_string(file)?;
+    let config: Config = serde_json::from_str(&config_contents)
+        .map_err(|err| io::Error::from(Error::Config(err.to_string())))?;
+    Ok(config)
+}
 
-    // Check if the new path is already in the PATH
-    if !current_path.split(';').any(|path| path == new_path) {
-        // Append the new path to the existing PATH
-        let new_path_value = if current_path.ends_with(';') {
-            format!("{}{}", current_path, new_path)
-        } else {
-            format!("{};{}", current_path, new_path)
-        };
+/// Reads the PATH of a scope from the registry as is, `%VAR%`s unexpanded
+pub fn read_path(scope: Scope) -> io::Result<String> {
+    Ok(read_var(scope, "Path")?.unwrap_or_default())
+}
 
-        // Set the new PATH value in the registry
-        environment_key.set_value("Path", &new_path_value)?;
+/// Reads an environment variable of a scope from the registry, `%VAR%`s unexpanded
+#[cfg(windows)]
+pub fn read_var(scope: Scope, name: &str) -> io::Result<Option<String>> {
+    let environment_key = scope.environment_key(KEY_READ)?;
+    match environment_key.get_raw_value(name) {
+        Ok(raw) => Ok(Some(from_reg_string(&raw.bytes))),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(Error::registry(err)),
+    }
+}
 
-        // Notify the system that the environment variables have changed
-        unsafe {
-            winapi::um::winuser::SendMessageTimeoutA(
-                winapi::um::winuser::HWND_BROADCAST,
-                winapi::um::winuser::WM_SETTINGCHANGE,
-                0 as winapi::shared::minwindef::WPARAM,
-                "Environment\0".as_ptr() as winapi::shared::minwindef::LPARAM,
-                winapi::um::winuser::SMTO_ABORTIFHUNG,
-                5000,
-                std::ptr::null_mut(),
-            );
-        }
+/// Writes an environment variable of a scope to the registry and notifies running apps.
+///
+/// Keeps a REG_EXPAND_SZ (the Windows default for PATH) so `%USERPROFILE%\...` values
+/// keep expanding, only using REG_SZ when there's nothing to expand.
+#[cfg(windows)]
+pub fn write_var(scope: Scope, name: &str, value: &str) -> io::Result<()> {
+    scope.check_elevation()?;
+    let environment_key = scope.environment_key(KEY_READ | KEY_WRITE)?;
+    let vtype = match environment_key.get_raw_value(name) {
+        Ok(raw) if raw.vtype == REG_SZ && !value.contains('%') => REG_SZ,
+        Ok(_) => REG_EXPAND_SZ,
+        Err(_) if value.contains('%') || name.eq_ignore_ascii_case("Path") => REG_EXPAND_SZ,
+        Err(_) => REG_SZ,
+    };
+    environment_key
+        .set_raw_value(
+            name,
+            &RegValue {
+                bytes: to_reg_string(value),
+                vtype,
+            },
+        )
+        .map_err(Error::registry)?;
+
+    broadcast_env_change();
+    Ok(())
+}
 
-        let msg = format!("Successfully added '{}' to the PATH.", new_path).green();
-        println!("{}", msg);
-    } else {
-        let msg = format!("'{}' is already in the PATH, skipping.", new_path).yellow();
-        println!("{}", msg);
-    }
+#[cfg(windows)]
+pub fn delete_var(scope: Scope, name: &str) -> io::Result<()> {
+    scope.check_elevation()?;
+    let environment_key = scope.environment_key(KEY_READ | KEY_WRITE)?;
+    environment_key
+        .delete_value(name)
+        .map_err(Error::registry)?;
 
+    broadcast_env_change();
     Ok(())
 }
 
+/// Names of the environment variables of a scope
+#[cfg(windows)]
+pub fn var_names(scope: Scope) -> io::Result<Vec<String>> {
+    let environment_key = scope.environment_key(KEY_READ)?;
+    environment_key
+        .enum_values()
+        .map(|value| value.map(|(name, _)| name).map_err(Error::registry))
+        .collect()
+}
+
+// Notify the system that the environment variables have changed
+#[cfg(windows)]
+fn broadcast_env_change() {
+    unsafe {
+        winapi::um::winuser::SendMessageTimeoutA(
+            winapi::um::winuser::HWND_BROADCAST,
+            winapi::um::winuser::WM_SETTINGCHANGE,
+            0 as winapi::shared::minwindef::WPARAM,
+            "Environment\0".as_ptr() as winapi::shared::minwindef::LPARAM,
+            winapi::um::winuser::SMTO_ABORTIFHUNG,
+            5000,
+            std::ptr::null_mut(),
+        );
+    }
+}
+
+#[cfg(windows)]
+fn from_reg_string(bytes: &[u8]) -> String {
+    let wide: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16_lossy(&wide)
+        .trim_end_matches('\0')
+        .to_string()
+}
+
+#[cfg(windows)]
+fn to_reg_string(value: &str) -> Vec<u8> {
+    value
+        .encode_utf16()
+        .chain([0])
+        .flat_map(|unit| unit.to_le_bytes())
+        .collect()
+}
+
+// Elsewhere there's no registry, which keeps the rest buildable and testable on Linux
+#[cfg(not(windows))]
+pub fn read_var(_scope: Scope, _name: &str) -> io::Result<Option<String>> {
+    Err(no_registry())
+}
+
+#[cfg(not(windows))]
+pub fn write_var(_scope: Scope, _name: &str, _value: &str) -> io::Result<()> {
+    Err(no_registry())
+}
+
+#[cfg(not(windows))]
+pub fn delete_var(_scope: Scope, _name: &str) -> io::Result<()> {
+    Err(no_registry())
+}
+
+#[cfg(not(windows))]
+pub fn var_names(_scope: Scope) -> io::Result<Vec<String>> {
+    Err(no_registry())
+}
+
+#[cfg(not(windows))]
+fn no_registry() -> io::Error {
+    Error::registry(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "the registry only exists on Windows",
+    ))
+}
+
+pub fn add_to_path_permanently(new_path: &str) -> io::Result<()> {
+    path_env::add(&mut RegistryEnv(Scope::User), new_path)
+}
+
 fn setup_rhiza_config() -> io::Result<()> {
-    // Resolve the home directory using shellexpand
-    let rhiza_dir = tilde("~/.rhiza").to_string();
-    let config_file = Path::new(&rhiza_dir).join("config.json");
+    let rhiza_dir = paths::home();
+    let config_file = paths::config_file();
 
-    // Create the .rhiza directory if it doesn't exist
-    if !Path::new(&rhiza_dir).exists() {
-        fs::create_dir(&rhiza_dir)?;
+    // Create the rhiza home if it doesn't exist
+    if !rhiza_dir.exists() {
+        fs::create_dir_all(rhiza_dir)?;
         let msg = format!("Created directory: {:?}", rhiza_dir).green();
-        println!("{}", msg);
+        say!("{}", msg);
     } else {
         let msg = format!("Directory already exists: {:?}, skipping", rhiza_dir).yellow();
-        println!("{}", msg);
+        say!("{}", msg);
     }
 
     // Create the config.json file if it doesn't exist
@@ -179,45 +687,34 @@ fn setup_rhiza_config() -> io::Result<()> {
         fs::write(&config_file, default_config)?;
 
         let msg = format!("Created file: {:?}", config_file).green();
-        println!("{}", msg);
+        say!("{}", msg);
     } else {
         let msg = format!("File already exists: {:?}, skipping", config_file).yellow();
-        println!("{}", msg);
+        say!("{}", msg);
     }
 
     Ok(())
 }
 
 pub fn read_shortcut(lnk_path: &str) -> Option<String> {
-    if lnk_path.ends_with(".lnk") {
+    let lower = lnk_path.to_lowercase();
+    if lower.ends_with(".lnk") {
         if let Ok(link) = LNKParser::from_path(lnk_path) {
             return link.get_target_full_path().clone();
         }
-    } else if lnk_path.ends_with(".url") {
-        if let Ok(content) = fs::read_to_string(lnk_path) {
-            let lines = content.lines();
-            let mut in_internet_shortcut_section = false;
-            for line in lines {
-                if line.trim() == "[InternetShortcut]" {
-                    in_internet_shortcut_section = true;
-                    continue;
-                }
-
-                if in_internet_shortcut_section && line.starts_with("URL=") {
-                    let url = line.trim_start_matches("URL=").trim().to_string();
-                    return Some(url);
-                }
-            }
+    } else if lower.ends_with(".url") {
+        if let Ok(url_file) = UrlFile::read(lnk_path) {
+            return url_file.url().map(|url| url.to_string());
         }
     }
     None
 }
 pub fn setup_panic_logging() {
-    let log_dir = std::path::PathBuf::from(shellexpand::tilde("~/.rhiza").into_owned());
-    let log_path = log_dir.join("panic.log");
+    let log_dir = paths::home();
+    let log_path = paths::panic_log();
 
     if !log_dir.exists() {
-        fs::create_dir_all(&log_dir).expect("Failed to create .rhiza directory");
+        fs::create_dir_all(log_dir).expect("Failed to create the rhiza home");
     }
 
     std::panic::set_hook(Box::new(move |panic_info| {
@@ -232,3 +729,42 @@ pub fn setup_panic_logging() {
         }
     }));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(keys: &[&str]) -> Profile {
+        let commands = keys
+            .iter()
+            .map(|key| (key.to_string(), Entry::new(format!("C:\\{}.exe", key))))
+            .collect();
+        Profile { commands }
+    }
+
+    #[test]
+    fn applied_leaves_out_inactive_profiles() {
+        let mut config = Config {
+            commands: profile(&["code", "steam"]).commands,
+            ..Default::default()
+        };
+        config
+            .profiles
+            .insert("work".to_string(), profile(&["code", "teams"]));
+        config
+            .profiles
+            .insert("games".to_string(), profile(&["discord"]));
+        config.active = vec!["work".to_string()];
+
+        let applied = config.applied();
+        let mut keys: Vec<&String> = applied.commands.keys().collect();
+        keys.sort();
+        assert_eq!(keys, ["code", "teams"]);
+
+        assert!(applied.is_inactive("steam"));
+        assert!(applied.is_inactive("discord"));
+        assert!(!applied.is_inactive("code"));
+        // Files rhiza knows nothing about aren't inactive
+        assert!(!applied.is_inactive("notes"));
+    }
+}
@@ -0,0 +1,621 @@
+use clap::ArgMatches;
+use colored::Colorize;
+use inquire::Confirm;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::installer::{self, Scope};
+use crate::path_env::{self, EnvStore, RegistryEnv};
+use crate::paths;
+
+/// One command as recorded in `<rhiza home>\journal\<id>.json`
+#[derive(Serialize, Deserialize)]
+struct Operation {
+    command: String,
+    #[serde(default)]
+    undone: bool,
+    /// config.json before and after
+    config: Option<(String, String)>,
+    #[serde(default)]
+    created: Vec<PathBuf>,
+    /// Deleted files, with the name of their backup in `<id>\`
+    #[serde(default)]
+    deleted: Vec<(PathBuf, String)>,
+    /// Overwritten files, with the name of their backup in `<id>\`
+    #[serde(default)]
+    changed: Vec<(PathBuf, String)>,
+    #[serde(default)]
+    vars: Vec<VarChange>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct VarChange {
+    scope: Scope,
+    name: String,
+    before: Option<String>,
+    after: Option<String>,
+}
+
+/// How many operations the journal keeps, older ones are dropped with their backups
+const MAX_OPERATIONS: usize = 100;
+
+/// The files a command can change, the only ones read before and after it
+#[derive(Debug, PartialEq)]
+enum Touches {
+    /// Only config.json and variables
+    Config,
+    /// The links, shims and icons of one command
+    Key(String),
+    /// Everything linked
+    All,
+}
+
+/// The state rhiza can change, taken before a command runs
+pub struct Recorder {
+    command: String,
+    touches: Touches,
+    config: String,
+    files: HashMap<PathBuf, Vec<u8>>,
+    vars: HashMap<(Scope, String), String>,
+}
+
+impl Recorder {
+    /// Remembers the current state if `matches` is a command that changes something
+    pub fn start(matches: &ArgMatches) -> Option<Recorder> {
+        let touches = touches(matches)?;
+        Some(Recorder {
+            command: std::env::args().skip(1).collect::<Vec<_>>().join(" "),
+            config: fs::read_to_string(paths::config_file()).unwrap_or_default(),
+            files: files(&touches),
+            vars: vars(),
+            touches,
+        })
+    }
+
+    /// Journals what changed since `start`, nothing if the command didn't change anything
+    pub fn finish(self) -> io::Result<()> {
+        let config = fs::read_to_string(paths::config_file()).unwrap_or_default();
+        let files_after = files(&self.touches);
+        let vars_after = vars();
+
+        let mut operation = Operation {
+            command: self.command,
+            undone: false,
+            config: (config != self.config).then_some((self.config, config)),
+            created: Vec::new(),
+            deleted: Vec::new(),
+            changed: Vec::new(),
+            vars: Vec::new(),
+        };
+
+        let mut backups = Vec::new();
+        for (file, content) in &self.files {
+            match files_after.get(file) {
+                Some(after) if after == content => {}
+                Some(_) => {
+                    operation
+                        .changed
+                        .push((file.clone(), backups.len().to_string()));
+                    backups.push(content);
+                }
+                None => {
+                    operation
+                        .deleted
+                        .push((file.clone(), backups.len().to_string()));
+                    backups.push(content);
+                }
+            }
+        }
+        operation.created = files_after
+            .keys()
+            .filter(|file| !self.files.contains_key(*file))
+            .cloned()
+            .collect();
+        operation.created.sort();
+        operation.deleted.sort();
+        operation.changed.sort();
+
+        let mut names: Vec<&(Scope, String)> = self.vars.keys().chain(vars_after.keys()).collect();
+        names.sort_by_key(|(scope, name)| (scope.name(), name.to_lowercase()));
+        names.dedup();
+        for key in names {
+            let (before, after) = (self.vars.get(key), vars_after.get(key));
+            if before != after {
+                operation.vars.push(VarChange {
+                    scope: key.0,
+                    name: key.1.clone(),
+                    before: before.cloned(),
+                    after: after.cloned(),
+                });
+            }
+        }
+
+        if operation.config.is_none()
+            && operation.created.is_empty()
+            && operation.deleted.is_empty()
+            && operation.changed.is_empty()
+            && operation.vars.is_empty()
+        {
+            return Ok(());
+        }
+
+        let journal = paths::journal_dir();
+        fs::create_dir_all(&journal)?;
+        let id = new_id(&journal);
+        if !backups.is_empty() {
+            let backup_dir = journal.join(&id);
+            fs::create_dir_all(&backup_dir)?;
+            for (n, content) in backups.into_iter().enumerate() {
+                fs::write(backup_dir.join(n.to_string()), content)?;
+            }
+        }
+        write(&id, &operation)?;
+        prune(MAX_OPERATIONS)
+    }
+}
+
+/// Prints the journal, newest first
+pub fn log() -> io::Result<()> {
+    let ids = ids()?;
+    if ids.is_empty() {
+        say!("{}", "The journal is empty".yellow());
+        return Ok(());
+    }
+
+    for id in ids {
+        let operation = read(&id)?;
+        let time = chrono::NaiveDateTime::parse_from_str(&id[..15], "%Y%m%d-%H%M%S")
+            .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        let command = format!("rhz {}", operation.command);
+        let command = if operation.undone {
+            format!("{} {}", command.dimmed(), "[undone]".yellow())
+        } else {
+            command.bold().to_string()
+        };
+        say!("{} {} {}", id.blue(), time, command);
+        for line in summary(&operation) {
+            say!("    {}", line);
+        }
+    }
+    Ok(())
+}
+
+/// Reverts the last operation that wasn't undone yet
+pub fn undo() -> io::Result<()> {
+    let Some((id, mut operation)) = ids()?
+        .into_iter()
+        .map(|id| read(&id).map(|operation| (id, operation)))
+        .collect::<io::Result<Vec<_>>>()?
+        .into_iter()
+        .find(|(_, operation)| !operation.undone)
+    else {
+        say!("{}", "Nothing to undo".yellow());
+        return Ok(());
+    };
+
+    say!("{}", format!("rhz {}", operation.command).bold());
+    for line in summary(&operation) {
+        say!("    {}", line);
+    }
+    let confirmed = Confirm::new("Undo it?")
+        .with_default(false)
+        .prompt()
+        .unwrap_or(false);
+    if !confirmed {
+        return Ok(());
+    }
+
+    // An empty `before` is a config the command created
+    if let Some((before, after)) = operation
+        .config
+        .as_ref()
+        .filter(|(before, _)| !before.is_empty())
+    {
+        if fs::read_to_string(paths::config_file()).unwrap_or_default() != *after {
+            say!(
+                "{}",
+                "config.json changed since, restoring it anyway".yellow()
+            );
+        }
+        fs::write(paths::config_file(), before)?;
+    }
+
+    for file in &operation.created {
+        if file.exists() {
+            fs::remove_file(file)?;
+            say!("  {} {}", "Deleted".yellow(), file.display());
+        }
+    }
+    let backup_dir = paths::journal_dir().join(&id);
+    for (file, backup) in operation.deleted.iter().chain(&operation.changed) {
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(backup_dir.join(backup), file)?;
+        say!("  {} {}", "Restored".green(), file.display());
+    }
+
+    for change in &operation.vars {
+        undo_var(change)?;
+    }
+
+    operation.undone = true;
+    write(&id, &operation)?;
+    say!("{}", format!("Undid 'rhz {}'", operation.command).green());
+    Ok(())
+}
+
+fn undo_var(change: &VarChange) -> io::Result<()> {
+    let mut store = RegistryEnv(change.scope);
+    let current = store.get(&change.name)?;
+
+    let value = if current == change.after {
+        change.before.clone()
+    } else if change.name.eq_ignore_ascii_case("Path") {
+        // Keep what was added to the PATH since, only reverting this operation's entries
+        let before = path_env::split(change.before.as_deref().unwrap_or_default());
+        let after = path_env::split(change.after.as_deref().unwrap_or_default());
+        let normalized = |entries: &[String]| -> Vec<String> {
+            entries
+                .iter()
+                .map(|entry| path_env::normalize(entry))
+                .collect()
+        };
+        let (before_keys, after_keys) = (normalized(&before), normalized(&after));
+
+        let mut entries: Vec<String> = path_env::split(current.as_deref().unwrap_or_default())
+            .into_iter()
+            .filter(|entry| {
+                let key = path_env::normalize(entry);
+                before_keys.contains(&key) || !after_keys.contains(&key)
+            })
+            .collect();
+        for entry in before {
+            let key = path_env::normalize(&entry);
+            if !after_keys.contains(&key) && !normalized(&entries).contains(&key) {
+                entries.push(entry);
+            }
+        }
+        Some(entries.join(";"))
+    } else {
+        say!(
+            "{}",
+            format!("{} changed since, leaving it as is", change.name).yellow()
+        );
+        return Ok(());
+    };
+
+    match value {
+        Some(value) if Some(&value) != current.as_ref() => store.set(&change.name, &value)?,
+        None if current.is_some() => store.unset(&change.name)?,
+        _ => return Ok(()),
+    }
+    say!("  {} {}", "Restored".green(), change.name);
+    Ok(())
+}
+
+/// What an operation changed, one line per kind of change
+fn summary(operation: &Operation) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    if let Some((before, after)) = &operation.config {
+        let parse = |config: &str| -> HashMap<String, Value> {
+            serde_json::from_str(config).unwrap_or_default()
+        };
+        let (before, after) = (parse(before), parse(after));
+        let commands = |config: &HashMap<String, Value>| -> HashMap<String, Value> {
+            config
+                .get("commands")
+                .and_then(|commands| serde_json::from_value(commands.clone()).ok())
+                .unwrap_or_default()
+        };
+
+        // Commands one by one, then any other setting that changed
+        let mut changes = changed_keys(&commands(&before), &commands(&after));
+        changes.extend(
+            changed_keys(&before, &after)
+                .into_iter()
+                .filter(|(key, _)| key != "commands"),
+        );
+
+        let changes: Vec<String> = changes
+            .into_iter()
+            .map(|(key, change)| {
+                let change = format!("{}{}", change, key);
+                match change.chars().next() {
+                    Some('+') => change.green().to_string(),
+                    Some('-') => change.red().to_string(),
+                    _ => change.yellow().to_string(),
+                }
+            })
+            .collect();
+        lines.push(format!("config: {}", changes.join(" ")));
+    }
+
+    let files = [
+        (operation.created.len(), "created".green()),
+        (operation.deleted.len(), "deleted".red()),
+        (operation.changed.len(), "changed".yellow()),
+    ];
+    let files: Vec<String> = files
+        .iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, label)| format!("{} {}", count, label))
+        .collect();
+    if !files.is_empty() {
+        lines.push(format!("files: {}", files.join(", ")));
+    }
+
+    for change in &operation.vars {
+        let name = match change.scope {
+            Scope::User => change.name.clone(),
+            Scope::Machine => format!("{} (machine)", change.name),
+        };
+        lines.push(format!("env: {}", name));
+    }
+    lines
+}
+
+/// The keys added (`+`), removed (`-`) or changed (`~`) between two maps, sorted
+fn changed_keys(
+    before: &HashMap<String, Value>,
+    after: &HashMap<String, Value>,
+) -> Vec<(String, char)> {
+    let mut keys: Vec<&String> = before.keys().chain(after.keys()).collect();
+    keys.sort();
+    keys.dedup();
+
+    keys.into_iter()
+        .filter_map(|key| match (before.get(key), after.get(key)) {
+            (None, Some(_)) => Some((key.to_string(), '+')),
+            (Some(_), None) => Some((key.to_string(), '-')),
+            (Some(old), Some(new)) if old != new => Some((key.to_string(), '~')),
+            _ => None,
+        })
+        .collect()
+}
+
+/// What a command can change, `None` for the ones that only read
+fn touches(matches: &ArgMatches) -> Option<Touches> {
+    let key = |matches: &ArgMatches| match matches.get_one::<String>("name") {
+        Some(name) => Touches::Key(name.to_string()),
+        // Picked from a list once it runs
+        None => Touches::All,
+    };
+    match matches.subcommand()? {
+        ("path", sub_matches) => match sub_matches.subcommand_name() {
+            Some("list" | "history") => None,
+            None if sub_matches.get_flag("file") => Some(Touches::All),
+            _ => Some(Touches::Config),
+        },
+        ("env", sub_matches) => matches!(
+            sub_matches.subcommand_name(),
+            Some("set" | "unset" | "apply" | "restore")
+        )
+        .then_some(Touches::Config),
+        ("profile", sub_matches) => match sub_matches.subcommand_name() {
+            Some("create") => Some(Touches::Config),
+            Some("use") => Some(Touches::All),
+            _ => None,
+        },
+        ("sync", sub_matches) => match sub_matches.subcommand_name()? {
+            "push" => Some(Touches::Config),
+            _ => Some(Touches::All),
+        },
+        ("doctor", sub_matches) => sub_matches.get_flag("fix").then_some(Touches::All),
+        ("url" | "lnk" | "rm", sub_matches) => Some(key(sub_matches)),
+        ("launch" | "outputs" | "category" | "clear-skipped", _) => Some(Touches::Config),
+        ("crawl" | "add" | "run", _) => Some(Touches::All),
+        _ => None,
+    }
+}
+
+/// The files rhiza writes that a command can change, with their content
+fn files(touches: &Touches) -> HashMap<PathBuf, Vec<u8>> {
+    let key = match touches {
+        Touches::Config => return HashMap::new(),
+        Touches::Key(key) => Some(key.as_str()),
+        Touches::All => None,
+    };
+    let is_touched = |path: &Path| {
+        key.is_none_or(|key| {
+            path.file_stem()
+                .is_some_and(|stem| stem.to_string_lossy().eq_ignore_ascii_case(key))
+        })
+    };
+
+    let config = installer::read_config().unwrap_or_default();
+    let mut dirs = Vec::new();
+    for scope in [Scope::User, Scope::Machine] {
+        dirs.push(PathBuf::from(scope.src_dir()));
+        dirs.push(PathBuf::from(scope.bin_dir()));
+        dirs.push(Path::new(&scope.root()).join("icons"));
+        if let Ok(root) = config.start_menu_root(scope) {
+            dirs.push(root);
+        }
+    }
+
+    let mut paths = vec![paths::placed_file()];
+    for entry in WalkDir::new(paths::shell_dir()).into_iter().flatten() {
+        paths.push(entry.into_path());
+    }
+    for dir in dirs {
+        for entry in WalkDir::new(dir).into_iter().flatten() {
+            if is_touched(entry.path()) {
+                paths.push(entry.into_path());
+            }
+        }
+    }
+    // Only the Desktop and Startup copies rhiza placed, the rest of those folders isn't its
+    let placed: Vec<PathBuf> = fs::read_to_string(paths::placed_file())
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default();
+    paths.extend(placed.into_iter().filter(|path| is_touched(path)));
+
+    let mut files = HashMap::new();
+    for path in paths {
+        if path.is_file() {
+            if let Ok(content) = fs::read(&path) {
+                files.insert(path, content);
+            }
+        }
+    }
+    files
+}
+
+/// The user variables and the machine PATH
+fn vars() -> HashMap<(Scope, String), String> {
+    let mut vars = HashMap::new();
+    let user = RegistryEnv(Scope::User);
+    for name in user.names().unwrap_or_default() {
+        if let Ok(Some(value)) = user.get(&name) {
+            vars.insert((Scope::User, name), value);
+        }
+    }
+    if let Ok(Some(path)) = RegistryEnv(Scope::Machine).get("Path") {
+        vars.insert((Scope::Machine, "Path".to_string()), path);
+    }
+    vars
+}
+
+fn new_id(journal: &Path) -> String {
+    let time = chrono::Local::now().format("%Y%m%d-%H%M%S").to_string();
+    let mut id = time.clone();
+    let mut n = 1;
+    while journal.join(format!("{}.json", id)).exists() {
+        n += 1;
+        id = format!("{}-{}", time, n);
+    }
+    id
+}
+
+/// The journaled operations, newest first
+fn ids() -> io::Result<Vec<String>> {
+    let mut ids: Vec<String> = match fs::read_dir(paths::journal_dir()) {
+        Ok(entries) => entries
+            .flatten()
+            .filter_map(|entry| {
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                let id = file_name.strip_suffix(".json")?;
+                path_env::is_snapshot_id(id).then(|| id.to_string())
+            })
+            .collect(),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => return Err(err),
+    };
+    // `20240101-120000-2` sorts after `20240101-120000`, so order by the numeric suffix too
+    ids.sort_by_key(|id| {
+        let n = id[15..].trim_start_matches('-').parse::<u32>().unwrap_or(1);
+        (id[..15].to_string(), n)
+    });
+    ids.reverse();
+    Ok(ids)
+}
+
+/// Drops all but the `keep` newest operations
+fn prune(keep: usize) -> io::Result<()> {
+    let journal = paths::journal_dir();
+    for id in ids()?.into_iter().skip(keep) {
+        fs::remove_file(journal.join(format!("{}.json", id)))?;
+        let backup_dir = journal.join(&id);
+        if backup_dir.exists() {
+            fs::remove_dir_all(backup_dir)?;
+        }
+    }
+    Ok(())
+}
+
+fn read(id: &str) -> io::Result<Operation> {
+    let content = fs::read_to_string(paths::journal_dir().join(format!("{}.json", id)))?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+fn write(id: &str, operation: &Operation) -> io::Result<()> {
+    let content = serde_json::to_string_pretty(operation)?;
+    fs::write(paths::journal_dir().join(format!("{}.json", id)), content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touches_of(args: &[&str]) -> Option<Touches> {
+        let matches = crate::cli()
+            .try_get_matches_from([&["rhz"], args].concat())
+            .unwrap();
+        touches(&matches)
+    }
+
+    #[test]
+    fn commands_only_touch_what_they_change() {
+        assert_eq!(touches_of(&["view"]), None);
+        assert_eq!(touches_of(&["path", "list"]), None);
+        assert_eq!(touches_of(&["path", "dedupe"]), Some(Touches::Config));
+        assert_eq!(touches_of(&["path", "--file"]), Some(Touches::All));
+        assert_eq!(
+            touches_of(&["launch", "foo", "attached"]),
+            Some(Touches::Config)
+        );
+        assert_eq!(
+            touches_of(&["rm", "foo"]),
+            Some(Touches::Key("foo".to_string()))
+        );
+        assert_eq!(touches_of(&["rm"]), Some(Touches::All));
+        assert_eq!(touches_of(&["run"]), Some(Touches::All));
+        assert_eq!(touches_of(&["doctor"]), None);
+    }
+
+    #[test]
+    fn keys_only_read_their_own_files() {
+        let src = PathBuf::from(Scope::User.src_dir());
+        fs::create_dir_all(&src).unwrap();
+        let own = src.join("journal-own.lnk");
+        let other = src.join("journal-other.lnk");
+        fs::write(&own, "own").unwrap();
+        fs::write(&other, "other").unwrap();
+
+        let touched = files(&Touches::Key("journal-own".to_string()));
+        assert_eq!(touched.get(&own).map(Vec::as_slice), Some(&b"own"[..]));
+        assert!(!touched.contains_key(&other));
+        assert!(files(&Touches::Config).is_empty());
+        assert!(files(&Touches::All).contains_key(&other));
+
+        fs::remove_file(own).unwrap();
+        fs::remove_file(other).unwrap();
+    }
+
+    #[test]
+    fn old_operations_are_dropped() {
+        let journal = paths::journal_dir();
+        fs::create_dir_all(&journal).unwrap();
+        let ids = ["20240101-120000", "20240101-120000-2", "20240102-080000"];
+        for id in ids {
+            let operation = Operation {
+                command: "run".to_string(),
+                undone: false,
+                config: None,
+                created: Vec::new(),
+                deleted: Vec::new(),
+                changed: Vec::new(),
+                vars: Vec::new(),
+            };
+            write(id, &operation).unwrap();
+            fs::create_dir_all(journal.join(id)).unwrap();
+        }
+
+        prune(2).unwrap();
+        assert_eq!(
+            super::ids().unwrap(),
+            ["20240102-080000", "20240101-120000-2"]
+        );
+        assert!(!journal.join("20240101-120000").exists());
+        assert!(journal.join("20240101-120000-2").exists());
+    }
+}
@@ -0,0 +1,486 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const HEADER_SIZE: u32 = 0x4C;
+const LINK_CLSID: [u8; 16] = [
+    0x01, 0x14, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0xC0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46,
+];
+
+// LinkFlags
+const HAS_LINK_TARGET_ID_LIST: u32 = 0x0000_0001;
+const HAS_LINK_INFO: u32 = 0x0000_0002;
+const HAS_NAME: u32 = 0x0000_0004;
+const HAS_RELATIVE_PATH: u32 = 0x0000_0008;
+const HAS_WORKING_DIR: u32 = 0x0000_0010;
+const HAS_ARGUMENTS: u32 = 0x0000_0020;
+const HAS_ICON_LOCATION: u32 = 0x0000_0040;
+const IS_UNICODE: u32 = 0x0000_0080;
+const RUN_AS_USER: u32 = 0x0000_2000;
+const HAS_EXP_ICON: u32 = 0x0000_4000;
+
+// LinkInfo
+const LINK_INFO_HEADER_SIZE: u32 = 0x24;
+const VOLUME_ID_AND_LOCAL_BASE_PATH: u32 = 0x1;
+const VOLUME_ID_SIZE: u32 = 0x11;
+const DRIVE_FIXED: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ShowCommand {
+    #[default]
+    Normal,
+    Maximized,
+    Minimized,
+}
+
+impl ShowCommand {
+    fn value(&self) -> u32 {
+        match self {
+            ShowCommand::Normal => 1,    // SW_SHOWNORMAL
+            ShowCommand::Maximized => 3, // SW_SHOWMAXIMIZED
+            ShowCommand::Minimized => 7, // SW_SHOWMINNOACTIVE
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<ShowCommand> {
+        match value.to_lowercase().as_str() {
+            "normal" => Some(ShowCommand::Normal),
+            "max" | "maximized" => Some(ShowCommand::Maximized),
+            "min" | "minimized" => Some(ShowCommand::Minimized),
+            _ => None,
+        }
+    }
+}
+
+/// A global hotkey, e.g `Ctrl+Alt+K`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HotKey {
+    key: u8,
+    modifiers: u8,
+}
+
+impl HotKey {
+    pub fn parse(value: &str) -> Option<HotKey> {
+        let mut modifiers = 0;
+        let mut key = None;
+
+        for part in value.split('+').map(|part| part.trim().to_uppercase()) {
+            match part.as_str() {
+                "SHIFT" => modifiers |= 0x01,
+                "CTRL" | "CONTROL" => modifiers |= 0x02,
+                "ALT" => modifiers |= 0x04,
+                _ if key.is_some() => return None,
+                _ => key = Some(virtual_key(&part)?),
+            }
+        }
+
+        // Windows ignores hotkeys without a modifier
+        if modifiers == 0 {
+            return None;
+        }
+        key.map(|key| HotKey { key, modifiers })
+    }
+
+    fn value(&self) -> u16 {
+        u16::from_le_bytes([self.key, self.modifiers])
+    }
+}
+
+fn virtual_key(name: &str) -> Option<u8> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c @ ('A'..='Z' | '0'..='9')), None) => return Some(c as u8),
+        (Some('F'), Some(_)) => {
+            let n: u8 = name[1..].parse().ok()?;
+            if (1..=24).contains(&n) {
+                return Some(0x70 + n - 1);
+            }
+        }
+        _ => {}
+    }
+    None
+}
+
+/// A Shell Link (.lnk) as described in MS-SHLLINK.
+///
+/// The target is stored in the LinkInfo structure (no IDList), so the output only depends on
+/// the fields below and can be produced and read back anywhere.
+#[derive(Debug, Clone, Default)]
+pub struct ShellLink {
+    pub target: PathBuf,
+    pub arguments: Option<String>,
+    pub working_dir: Option<String>,
+    pub description: Option<String>,
+    pub icon_location: Option<String>,
+    pub icon_index: i32,
+    pub hotkey: Option<HotKey>,
+    pub show_command: ShowCommand,
+    pub run_as_admin: bool,
+}
+
+impl ShellLink {
+    pub fn new<P: AsRef<Path>>(target: P) -> Self {
+        ShellLink {
+            target: target.as_ref().to_path_buf(),
+            ..Default::default()
+        }
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_bytes()?)
+    }
+
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        let target = self.target.to_string_lossy();
+        if !self.target.is_absolute() && !target.contains(":\\") {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Shortcut target must be an absolute path, got '{}'", target),
+            ));
+        }
+
+        let strings = [
+            (HAS_NAME, &self.description),
+            (HAS_WORKING_DIR, &self.working_dir),
+            (HAS_ARGUMENTS, &self.arguments),
+            (HAS_ICON_LOCATION, &self.icon_location),
+        ];
+
+        let mut flags = HAS_LINK_INFO | IS_UNICODE;
+        for (flag, value) in &strings {
+            if value.is_some() {
+                flags |= flag;
+            }
+        }
+        if self.run_as_admin {
+            flags |= RUN_AS_USER;
+        }
+
+        let mut out = Vec::new();
+
+        // ShellLinkHeader
+        put_u32(&mut out, HEADER_SIZE);
+        out.extend_from_slice(&LINK_CLSID);
+        put_u32(&mut out, flags);
+        put_u32(&mut out, 0); // FileAttributes
+        out.extend_from_slice(&[0; 24]); // CreationTime, AccessTime, WriteTime
+        put_u32(&mut out, 0); // FileSize
+        out.extend_from_slice(&self.icon_index.to_le_bytes());
+        put_u32(&mut out, self.show_command.value());
+        out.extend_from_slice(&self.hotkey.map_or(0, |hotkey| hotkey.value()).to_le_bytes());
+        out.extend_from_slice(&[0; 10]); // Reserved1, Reserved2, Reserved3
+
+        link_info(&mut out, &target);
+
+        // StringData, in the order the spec requires
+        for (_, value) in &strings {
+            if let Some(value) = value {
+                let units: Vec<u16> = value.encode_utf16().collect();
+                let count = u16::try_from(units.len()).map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "Shortcut string is too long")
+                })?;
+                out.extend_from_slice(&count.to_le_bytes());
+                out.extend(units.iter().flat_map(|unit| unit.to_le_bytes()));
+            }
+        }
+
+        // TerminalBlock, no ExtraData
+        put_u32(&mut out, 0);
+
+        Ok(out)
+    }
+}
+
+/// Points an existing shortcut at another icon, keeping everything else as it was
+pub fn set_icon(bytes: &[u8], location: &str, index: i32) -> io::Result<Vec<u8>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Not a valid shortcut");
+    let read_u16 = |at: usize| {
+        bytes
+            .get(at..at + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+            .ok_or_else(invalid)
+    };
+    if bytes.get(..4) != Some(&HEADER_SIZE.to_le_bytes()) || bytes.get(4..20) != Some(&LINK_CLSID) {
+        return Err(invalid());
+    }
+    let flags = u32::from_le_bytes([bytes[20], bytes[21], bytes[22], bytes[23]]);
+    let unicode = flags & IS_UNICODE != 0;
+
+    let mut at = HEADER_SIZE as usize;
+    if flags & HAS_LINK_TARGET_ID_LIST != 0 {
+        at += 2 + read_u16(at)?;
+    }
+    if flags & HAS_LINK_INFO != 0 {
+        let size = bytes.get(at..at + 4).ok_or_else(invalid)?;
+        at += u32::from_le_bytes([size[0], size[1], size[2], size[3]]) as usize;
+    }
+    // The strings before IconLocation, which is the last one
+    for flag in [HAS_NAME, HAS_RELATIVE_PATH, HAS_WORKING_DIR, HAS_ARGUMENTS] {
+        if flags & flag != 0 {
+            at += 2 + read_u16(at)? * if unicode { 2 } else { 1 };
+        }
+    }
+    let rest = if flags & HAS_ICON_LOCATION != 0 {
+        at + 2 + read_u16(at)? * if unicode { 2 } else { 1 }
+    } else {
+        at
+    };
+    let (head, tail) = (
+        bytes.get(..at).ok_or_else(invalid)?,
+        bytes.get(rest..).ok_or_else(invalid)?,
+    );
+
+    let mut out = head.to_vec();
+    // An IconEnvironmentDataBlock would take precedence over the new location
+    let flags = (flags | HAS_ICON_LOCATION) & !HAS_EXP_ICON;
+    out[20..24].copy_from_slice(&flags.to_le_bytes());
+    out[56..60].copy_from_slice(&index.to_le_bytes());
+
+    let location: Vec<u8> = if unicode {
+        location.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    } else {
+        location
+            .chars()
+            .map(|c| if c.is_ascii() { c as u8 } else { b'?' })
+            .collect()
+    };
+    let count = location.len() / if unicode { 2 } else { 1 };
+    let count = u16::try_from(count)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Shortcut string is too long"))?;
+    out.extend_from_slice(&count.to_le_bytes());
+    out.extend(location);
+    out.extend_from_slice(tail);
+    Ok(out)
+}
+
+fn link_info(out: &mut Vec<u8>, target: &str) {
+    let ansi_path: Vec<u8> = target
+        .chars()
+        .map(|c| if c.is_ascii() { c as u8 } else { b'?' })
+        .chain([0])
+        .collect();
+    let unicode_path: Vec<u8> = target
+        .encode_utf16()
+        .chain([0])
+        .flat_map(|unit| unit.to_le_bytes())
+        .collect();
+
+    let volume_id_offset = LINK_INFO_HEADER_SIZE;
+    let local_base_path_offset = volume_id_offset + VOLUME_ID_SIZE;
+    let common_path_suffix_offset = local_base_path_offset + ansi_path.len() as u32;
+    let local_base_path_unicode_offset = common_path_suffix_offset + 1;
+    let common_path_suffix_unicode_offset =
+        local_base_path_unicode_offset + unicode_path.len() as u32;
+    let size = common_path_suffix_unicode_offset + 2;
+
+    put_u32(out, size);
+    put_u32(out, LINK_INFO_HEADER_SIZE);
+    put_u32(out, VOLUME_ID_AND_LOCAL_BASE_PATH);
+    put_u32(out, volume_id_offset);
+    put_u32(out, local_base_path_offset);
+    put_u32(out, 0); // CommonNetworkRelativeLinkOffset
+    put_u32(out, common_path_suffix_offset);
+    put_u32(out, local_base_path_unicode_offset);
+    put_u32(out, common_path_suffix_unicode_offset);
+
+    // VolumeID with an empty label
+    put_u32(out, VOLUME_ID_SIZE);
+    put_u32(out, DRIVE_FIXED);
+    put_u32(out, 0); // DriveSerialNumber
+    put_u32(out, 0x10); // VolumeLabelOffset
+    out.push(0);
+
+    out.extend_from_slice(&ansi_path);
+    out.push(0); // CommonPathSuffix
+    out.extend_from_slice(&unicode_path);
+    out.extend_from_slice(&[0, 0]); // CommonPathSuffixUnicode
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lnk_parser::LNKParser;
+    use std::io::Cursor;
+
+    fn sample() -> ShellLink {
+        ShellLink {
+            arguments: Some("--profile \"work\"".to_string()),
+            working_dir: Some("C:\\Tools".to_string()),
+            description: Some("Täst tool".to_string()),
+            icon_location: Some("C:\\Tools\\tool.ico".to_string()),
+            icon_index: 2,
+            hotkey: HotKey::parse("Ctrl+Alt+K"),
+            show_command: ShowCommand::Maximized,
+            run_as_admin: true,
+            ..ShellLink::new("C:\\Tools\\tool.exe")
+        }
+    }
+
+    /// Parses `bytes` with lnk_parser, checking it consumed all of them
+    fn parse(bytes: &[u8]) -> LNKParser {
+        let mut reader = Cursor::new(bytes);
+        let link = LNKParser::from_reader(&mut reader).expect("lnk_parser rejected the link");
+        assert_eq!(reader.position(), bytes.len() as u64);
+        link
+    }
+
+    macro_rules! string {
+        ($data:expr) => {
+            $data.as_ref().map(|data| data.string.as_str())
+        };
+    }
+
+    #[test]
+    fn header_round_trips() {
+        let bytes = sample().to_bytes().unwrap();
+        let link = parse(&bytes);
+        let header = link.get_shell_link_header();
+
+        assert_eq!(header.header_size, HEADER_SIZE);
+        assert_eq!(&bytes[4..20], &LINK_CLSID);
+        assert!(header.flags.HasLinkInfo && header.flags.IsUnicode && header.flags.RunAsUser);
+        assert!(!header.flags.HasLinkTargetIDList && !header.flags.HasRelativePath);
+        assert!(header.flags.HasName && header.flags.HasWorkingDir);
+        assert!(header.flags.HasArguments && header.flags.HasIconLocation);
+        assert_eq!(header.icon_index, 2);
+        assert_eq!(header.file_size, 0);
+        assert_eq!(&bytes[0x42..HEADER_SIZE as usize], &[0; 10]);
+    }
+
+    #[test]
+    fn link_info_round_trips() {
+        let bytes = sample().to_bytes().unwrap();
+        let link = parse(&bytes);
+        let info = link.get_link_info().as_ref().unwrap();
+
+        let size = u32::from_le_bytes(bytes[0x4C..0x50].try_into().unwrap());
+        assert_eq!(info.size, size);
+        assert_eq!(info.header_size, LINK_INFO_HEADER_SIZE);
+        assert_eq!(info.volume_id_offset, LINK_INFO_HEADER_SIZE);
+        assert_eq!(info.common_network_relative_link_offset, 0);
+        assert!(info.volume_id.is_some());
+        assert_eq!(info.local_base_path.as_deref(), Some("C:\\Tools\\tool.exe"));
+        // lnk_parser reads an empty CommonPathSuffix as none
+        assert_eq!(info.common_path_suffix.as_deref().unwrap_or(""), "");
+        assert_eq!(
+            link.get_target_full_path().as_deref(),
+            Some("C:\\Tools\\tool.exe")
+        );
+    }
+
+    #[test]
+    fn string_data_round_trips() {
+        let link = parse(&sample().to_bytes().unwrap());
+
+        assert_eq!(string!(link.get_name_string()), Some("Täst tool"));
+        assert_eq!(string!(link.get_working_dir()), Some("C:\\Tools"));
+        assert_eq!(
+            string!(link.get_command_line_arguments()),
+            Some("--profile \"work\"")
+        );
+        assert_eq!(
+            string!(link.get_icon_location()),
+            Some("C:\\Tools\\tool.ico")
+        );
+        assert!(link.get_relative_path().is_none());
+    }
+
+    #[test]
+    fn missing_strings_are_left_out() {
+        let bytes = ShellLink::new("C:\\tool.exe").to_bytes().unwrap();
+        let link = parse(&bytes);
+        let flags = &link.get_shell_link_header().flags;
+
+        assert!(!flags.HasName && !flags.HasWorkingDir && !flags.RunAsUser);
+        assert!(!flags.HasArguments && !flags.HasIconLocation);
+        assert!(link.get_name_string().is_none());
+        assert!(link.get_icon_location().is_none());
+        // Just the TerminalBlock after the LinkInfo
+        assert_eq!(&bytes[bytes.len() - 4..], &[0; 4]);
+    }
+
+    #[test]
+    fn hotkey_round_trips() {
+        let bytes = sample().to_bytes().unwrap();
+        assert_eq!(&bytes[0x40..0x42], &[b'K', 0x06]);
+        let link = parse(&bytes);
+        assert_eq!(
+            link.get_shell_link_header().hot_key.to_string(),
+            "CTRL + ALT + K"
+        );
+
+        let f5 = ShellLink {
+            hotkey: HotKey::parse("shift+F5"),
+            ..ShellLink::new("C:\\tool.exe")
+        };
+        let link = parse(&f5.to_bytes().unwrap());
+        assert_eq!(
+            link.get_shell_link_header().hot_key.to_string(),
+            "SHIFT + F5"
+        );
+
+        let none = parse(&ShellLink::new("C:\\tool.exe").to_bytes().unwrap());
+        assert_eq!(none.get_shell_link_header().hot_key.to_string(), "");
+    }
+
+    #[test]
+    fn show_command_round_trips() {
+        for (show_command, value, parsed) in [
+            (ShowCommand::Normal, 1, "SHOWNORMAL"),
+            (ShowCommand::Maximized, 3, "SHOWMAXIMIZED"),
+            (ShowCommand::Minimized, 7, "SHOWMINNOACTIVE"),
+        ] {
+            let link = ShellLink {
+                show_command,
+                ..ShellLink::new("C:\\tool.exe")
+            };
+            let bytes = link.to_bytes().unwrap();
+            assert_eq!(&bytes[0x3C..0x40], &(value as u32).to_le_bytes());
+            let header = parse(&bytes);
+            let header = header.get_shell_link_header();
+            assert_eq!(format!("{:?}", header.get_sc()), parsed);
+        }
+    }
+
+    #[test]
+    fn set_icon_matches_a_fresh_link() {
+        let mut link = sample();
+        let bytes = link.to_bytes().unwrap();
+
+        link.icon_location = Some("D:\\icons\\other.ico".to_string());
+        link.icon_index = -3;
+        let updated = set_icon(&bytes, "D:\\icons\\other.ico", -3).unwrap();
+        assert_eq!(updated, link.to_bytes().unwrap());
+
+        link.icon_location = None;
+        let without = link.to_bytes().unwrap();
+        let updated = set_icon(&without, "D:\\icons\\other.ico", -3).unwrap();
+        link.icon_location = Some("D:\\icons\\other.ico".to_string());
+        assert_eq!(updated, link.to_bytes().unwrap());
+    }
+
+    #[test]
+    fn set_icon_rejects_other_files() {
+        assert!(set_icon(b"not a shortcut", "C:\\icon.ico", 0).is_err());
+        let bytes = sample().to_bytes().unwrap();
+        assert!(set_icon(&bytes[..0x50], "C:\\icon.ico", 0).is_err());
+    }
+
+    #[test]
+    fn relative_targets_are_rejected() {
+        assert!(ShellLink::new("tool.exe").to_bytes().is_err());
+    }
+
+    #[test]
+    fn parses_hotkeys() {
+        assert_eq!(HotKey::parse("Ctrl+Alt+K").unwrap().value(), 0x064B);
+        assert_eq!(HotKey::parse("ctrl + f12").unwrap().value(), 0x027B);
+        assert!(HotKey::parse("K").is_none());
+        assert!(HotKey::parse("Ctrl+K+J").is_none());
+        assert!(HotKey::parse("Ctrl+F25").is_none());
+    }
+}
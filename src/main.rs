@@ -1,40 +1,75 @@
+#[macro_use]
+mod report;
+
+mod completions;
+mod doctor;
+mod env;
+mod error;
+mod hooks;
+mod icon;
 mod installer;
+mod journal;
+mod lnk;
+mod outputs;
+mod path_env;
+mod paths;
+mod pe;
+mod profile;
 mod searcher;
+mod shell;
+mod shim;
+mod shim_file;
+mod start_menu;
+mod sync;
+mod target;
+mod url_file;
 mod worker;
 
 use std::{path::Path, sync::mpsc::channel};
 
 use clap::{
     builder::{styling::AnsiColor, Styles},
-    ColorChoice, Command,
+    Arg, ArgAction, ArgMatches, ColorChoice, Command,
 };
 use colored::*;
-use inquire::Text;
+use error::Error;
+use hooks::Hook;
+use inquire::{Confirm, Text};
+use installer::{Config, Entry, LaunchMode, Output, Scope};
+use lnk::{HotKey, ShellLink, ShowCommand};
+use path_env::RegistryEnv;
 use searcher::prompt_fzf;
+use serde_json::json;
+use target::TargetKind;
 
 fn main() {
+    let matches = cli().get_matches();
+    paths::init(matches.get_one::<String>("config-dir").map(|s| s.as_str()));
     installer::setup_panic_logging();
-    let matches = Command::new("Rhiza")
-        .version("1.0")
-        .about("A blazingly fast app linker for Windows 🚀")
-        .color(ColorChoice::Always)
-        .styles(
-            Styles::styled()
-                .header(AnsiColor::Green.on_default().bold())
-                .literal(AnsiColor::Blue.on_default()),
-        )
-        .subcommand(Command::new("crawl").about("Find potential apps to link"))
-        .subcommand(Command::new("add").about("Search for a single app to add"))
-        .subcommand(Command::new("path").about("Search for a single app to add to path"))
-        .subcommand(Command::new("rm").about("Removed an key added by rhiza"))
-        .subcommand(Command::new("run").about("Create the lnk files"))
-        .subcommand(Command::new("view").about("View all linked apps and their config"))
-        .subcommand(
-            Command::new("clear-skipped")
-                .about("Clear the skipped config created the the crawl command"),
-        )
-        .get_matches();
+    report::set_json(matches.get_flag("json"));
 
+    let recorder = journal::Recorder::start(&matches);
+    let mut code = match dispatch(&matches) {
+        Ok(()) => 0,
+        Err(err) => {
+            report::error(&err);
+            err.exit_code()
+        }
+    };
+    // Failed commands are journaled too, they may have changed things before failing
+    if let Some(recorder) = recorder {
+        if let Err(err) = recorder.finish() {
+            report::error(format!("Couldn't write the journal: {}", err));
+            if code == 0 {
+                code = Error::from(err).exit_code();
+            }
+        }
+    }
+    report::finish(code);
+    std::process::exit(code);
+}
+
+fn dispatch(matches: &ArgMatches) -> error::Result<()> {
     match matches.subcommand() {
         Some(("crawl", sub_matches)) => {
             let dirs = match sub_matches.get_one::<String>("path") {
@@ -44,73 +79,893 @@ fn main() {
                     "C:\\ProgramData\\Microsoft\\Windows\\Start Menu",
                 ],
                 Some(p) => {
-                    let dir: &str = &p;
+                    let dir: &str = p;
                     vec![dir]
                 }
             };
-            let _ = worker::crawl_directory(dirs);
-            println!("{}", "Do 'rhz run' to apply the changes".purple().bold())
-        }
-        Some(("add", _)) => {
-            let mut config = installer::check().unwrap();
-
-            let res = searcher::search_prompt_fzf(
-                "Select app to add:\n",
-                vec!["exe".to_string(), "lnk".to_string(), "url".to_string()],
-            );
-
-            if let Some(path) = res {
-                if let Ok(name) = Text::new("what to call that?").prompt() {
-                    config.commands.insert(name, path);
-                    config.write().unwrap();
-                    println!("{}", "Do 'rhz run' to apply the changes".purple().bold())
+            let report = worker::crawl_directory(dirs)?;
+            let config = installer::read_config()?;
+            for candidate in &report.candidates {
+                if let Some(name) = &candidate.name {
+                    post_add(&config, name)?;
                 }
             }
+            report::emit(&report);
+            say!("{}", "Do 'rhz run' to apply the changes".purple().bold())
+        }
+        Some(("add", sub_matches)) => {
+            let mut config = installer::check()?;
+            let launch = LaunchMode::parse(sub_matches.get_one::<String>("launch").unwrap())
+                .unwrap_or_default();
+            let scope = scope_of(sub_matches).unwrap_or_default();
+            let category = sub_matches.get_one::<String>("category").cloned();
+
+            // Folders can't be searched for, only given
+            let path = match sub_matches.get_one::<String>("target") {
+                Some(target) => std::path::absolute(target)?.to_string_lossy().to_string(),
+                None => searcher::search_prompt_fzf(
+                    "Select app to add:\n",
+                    TargetKind::EXTENSIONS
+                        .iter()
+                        .map(|ext| ext.to_string())
+                        .collect(),
+                )?,
+            };
+            if TargetKind::detect(Path::new(&path)).is_none() {
+                return Err(Error::UnsupportedTarget(path));
+            }
+
+            let name = Text::new("what to call that?").prompt()?;
+            let mut entry = Entry::new(path);
+            entry.launch = launch;
+            entry.scope = scope;
+            entry.category = category;
+            if let Some(outputs) = outputs_of(sub_matches) {
+                entry.outputs = outputs;
+            }
+            emit_entry(&name, &entry);
+            config.current_mut().insert(name.clone(), entry);
+            config.write()?;
+            post_add(&config, &name)?;
+            say!("{}", "Do 'rhz run' to apply the changes".purple().bold())
         }
-        Some(("path", _)) => {
-            let res = searcher::search_prompt_fzf(
-                "Select path to add:\n",
-                vec!["ps1".to_string(), "exe".to_string()],
-            );
-
-            if let Some(path) = res {
-                let base_dir = Path::new(&path).parent();
-                if let Some(dir) = base_dir {
-                    let dir = &dir.to_string_lossy().to_string();
-                    installer::add_to_path_permanently(dir).unwrap();
+        Some(("path", sub_matches)) => {
+            let scope = scope_of(sub_matches).unwrap_or_default();
+            let mut store = RegistryEnv(scope);
+            let res = match sub_matches.subcommand() {
+                Some(("list", _)) => {
+                    let config = installer::read_config().unwrap_or_default();
+                    path_env::list(&store, &config)
                 }
+                Some(("dedupe", _)) => path_env::dedupe(&mut store),
+                Some(("prune-missing", _)) => path_env::prune_missing(&mut store),
+                Some(("move", move_matches)) => path_env::move_entry(
+                    &mut store,
+                    move_matches.get_one::<String>("entry").unwrap(),
+                    move_matches.get_one::<String>("before").unwrap(),
+                ),
+                Some(("edit", _)) => path_env::edit(&mut store),
+                Some(("history", _)) => path_env::history(&store, scope),
+                Some(("restore", restore_matches)) => path_env::restore(
+                    &mut store,
+                    scope,
+                    restore_matches.get_one::<String>("id").unwrap(),
+                ),
+                _ => {
+                    let path = searcher::search_prompt_fzf(
+                        "Select path to add:\n",
+                        vec!["ps1".to_string(), "exe".to_string()],
+                    )?;
+
+                    if sub_matches.get_flag("file") {
+                        let stem = Path::new(&path)
+                            .file_stem()
+                            .map(|stem| stem.to_string_lossy().to_string())
+                            .unwrap_or_default();
+                        let name = Text::new("what to call that?")
+                            .with_default(&stem)
+                            .prompt()?;
+                        if let Some(existing) = path_env::conflict(&name, None) {
+                            let msg = format!(
+                                "'{}' is also {}, the first in the PATH wins",
+                                name,
+                                existing.display()
+                            );
+                            say!("{}", msg.yellow());
+                        }
+                        let mut config = installer::check()?;
+                        let mut entry = Entry::new(path);
+                        entry.scope = scope;
+                        entry.outputs = vec![Output::Shim];
+                        emit_entry(&name, &entry);
+                        config.current_mut().insert(name.clone(), entry);
+                        config.write()?;
+                        post_add(&config, &name)?;
+                        say!("{}", "Do 'rhz run' to apply the changes".purple().bold());
+                        Ok(())
+                    } else if let Some(dir) = Path::new(&path).parent() {
+                        path_env::preview_dir(dir);
+                        let confirmed = Confirm::new("Add it to the PATH?")
+                            .with_default(true)
+                            .prompt()?;
+                        if !confirmed {
+                            return Ok(());
+                        }
+
+                        let dir = dir.to_string_lossy().to_string();
+                        let mut config = installer::check()?;
+                        path_env::add(&mut store, &dir)?;
+                        if !config.paths.contains(&dir) {
+                            config.paths.push(dir);
+                            config.write()?;
+                        }
+                        Ok(())
+                    } else {
+                        Ok(())
+                    }
+                }
+            };
+            res?;
+            // With --json, every path command ends with the resulting entries
+            if report::is_json() && sub_matches.subcommand_name() != Some("history") {
+                let config = installer::read_config().unwrap_or_default();
+                let entries = path_env::entries(&store, &config)?;
+                report::emit(&json!({ "scope": scope.name(), "entries": entries }));
             }
         }
+        Some(("url", sub_matches)) => {
+            let mut config = installer::check()?;
+            let name = sub_matches.get_one::<String>("name").unwrap();
+            let url = sub_matches.get_one::<String>("url").unwrap();
+            let icon = sub_matches.get_one::<String>("icon").map(|s| s.as_str());
+            let icon_index = *sub_matches.get_one::<i32>("icon-index").unwrap();
+            let scope = scope_of(sub_matches).unwrap_or_default();
+
+            let force = sub_matches.get_flag("force");
+            let path = worker::create_url(name, url, icon, icon_index, scope, force)?;
+            let mut entry = Entry::new(path);
+            entry.scope = scope;
+            entry.category = sub_matches.get_one::<String>("category").cloned();
+            if let Some(outputs) = outputs_of(sub_matches) {
+                entry.outputs = outputs;
+            }
+            emit_entry(name, &entry);
+            config.current_mut().insert(name.to_string(), entry);
+            config.write()?;
+            post_add(&config, name)?;
+            say!("{}", "Do 'rhz run' to apply the changes".purple().bold())
+        }
+        Some(("lnk", sub_matches)) => {
+            let mut config = installer::check()?;
+            let name = sub_matches.get_one::<String>("name").unwrap();
+            let target = sub_matches.get_one::<String>("target").unwrap();
+
+            let mut link = ShellLink::new(target);
+            link.arguments = sub_matches.get_one::<String>("args").cloned();
+            link.working_dir = sub_matches.get_one::<String>("dir").cloned();
+            link.description = sub_matches.get_one::<String>("description").cloned();
+            link.icon_location = sub_matches.get_one::<String>("icon").cloned();
+            link.icon_index = *sub_matches.get_one::<i32>("icon-index").unwrap();
+            link.show_command = ShowCommand::parse(sub_matches.get_one::<String>("show").unwrap())
+                .unwrap_or_default();
+            link.run_as_admin = sub_matches.get_flag("admin");
+            link.hotkey = sub_matches.get_one::<HotKey>("hotkey").copied();
+
+            let scope = scope_of(sub_matches).unwrap_or_default();
+            let force = sub_matches.get_flag("force");
+            let path = worker::create_lnk(name, &link, scope, force)?;
+            let mut entry = Entry::new(path);
+            entry.scope = scope;
+            entry.category = sub_matches.get_one::<String>("category").cloned();
+            if let Some(outputs) = outputs_of(sub_matches) {
+                entry.outputs = outputs;
+            }
+            emit_entry(name, &entry);
+            config.current_mut().insert(name.to_string(), entry);
+            config.write()?;
+            post_add(&config, name)?;
+            say!("{}", "Do 'rhz run' to apply the changes".purple().bold())
+        }
         Some(("view", _)) => {
-            let config = installer::check().unwrap();
-            let content = serde_json::to_string_pretty(&config.commands).unwrap();
-            println!("{}", content)
-        }
-        Some(("rm", _)) => {
-            let mut config = installer::check().unwrap();
-            let items: Vec<String> = config.commands.keys().cloned().collect();
-            let (tx, rx) = channel::<String>();
-            for item in items {
-                tx.send(item).unwrap();
+            let config = installer::check()?;
+            let commands = config.applied_commands();
+            let mut keys: Vec<&String> = commands.keys().collect();
+            keys.sort();
+            let mut view = Vec::new();
+            for key in keys {
+                let (profile, entry) = &commands[key];
+                let status = worker::status(key, entry);
+                view.push(json!({
+                    "name": key,
+                    "profile": profile,
+                    "entry": entry,
+                    "resolved_launch": entry.launch.resolve(&entry.target).name(),
+                    "status": status,
+                }));
+                let launch = match entry.launch {
+                    LaunchMode::Auto => {
+                        format!("auto, {}", entry.launch.resolve(&entry.target).name())
+                    }
+                    launch => launch.name().to_string(),
+                };
+                let launch = match entry.scope {
+                    Scope::User => launch,
+                    scope => format!("{}, {}", launch, scope.name()),
+                };
+                let launch = if entry.outputs != Output::defaults() {
+                    let outputs: Vec<&str> = entry.outputs.iter().map(|o| o.name()).collect();
+                    format!("{}, {}", launch, outputs.join("+"))
+                } else {
+                    launch
+                };
+                let launch = match &entry.category {
+                    Some(category) => format!("{}, {}", launch, category),
+                    None => launch,
+                };
+                // Only worth showing once there's more than the default profile
+                let launch = if config.profiles.is_empty() {
+                    launch
+                } else {
+                    format!("{}, {}", launch, profile)
+                };
+                let status = match status {
+                    "missing" => format!(" {}", "(missing)".red()),
+                    "pending" => format!(" {}", "(not linked yet)".yellow()),
+                    _ => String::new(),
+                };
+                say!(
+                    "{} -> {} {}{}",
+                    key.bold(),
+                    entry.target,
+                    format!("[{}]", launch).blue(),
+                    status
+                );
             }
-            drop(tx);
-            let key = prompt_fzf(rx, "Select key to remove").expect("Failed to get key for rm");
+            report::emit(&view);
+        }
+        Some(("launch", sub_matches)) => {
+            let mut config = installer::check()?;
+            let name = sub_matches.get_one::<String>("name").unwrap();
+            let launch = LaunchMode::parse(sub_matches.get_one::<String>("launch").unwrap())
+                .unwrap_or_default();
 
-            worker::remove_key(&key).unwrap();
-            config.commands.remove(&key);
-            config.write().unwrap();
+            let entry = config.command_mut(name).ok_or_else(|| not_found(name))?;
+            entry.launch = launch;
+            emit_entry(name, entry);
+            config.write()?;
+            say!("{}", "Do 'rhz run' to apply the changes".purple().bold())
+        }
+        Some(("outputs", sub_matches)) => {
+            let mut config = installer::check()?;
+            let name = sub_matches.get_one::<String>("name").unwrap();
+            let outputs = outputs_of(sub_matches).unwrap_or_default();
+
+            let entry = config.command_mut(name).ok_or_else(|| not_found(name))?;
+            entry.outputs = outputs;
+            emit_entry(name, entry);
+            config.write()?;
+            say!("{}", "Do 'rhz run' to apply the changes".purple().bold())
+        }
+        Some(("category", sub_matches)) => {
+            let mut config = installer::check()?;
+            let name = sub_matches.get_one::<String>("name").unwrap();
+            let category = sub_matches.get_one::<String>("category").cloned();
+
+            let entry = config.command_mut(name).ok_or_else(|| not_found(name))?;
+            entry.category = category;
+            emit_entry(name, entry);
+            config.write()?;
+            say!("{}", "Do 'rhz run' to apply the changes".purple().bold())
+        }
+        Some(("rm", sub_matches)) => {
+            let mut config = installer::check()?;
+            let key = match sub_matches.get_one::<String>("name") {
+                Some(name) => name.to_string(),
+                None => {
+                    let items: Vec<String> = config.applied_commands().into_keys().collect();
+                    let (tx, rx) = channel::<String>();
+                    for item in items {
+                        tx.send(item).unwrap();
+                    }
+                    drop(tx);
+                    prompt_fzf(rx, "Select key to remove")?
+                }
+            };
+
+            // Defaults to the scope the command was installed for
+            let scope = scope_of(sub_matches)
+                .or_else(|| {
+                    let commands = config.applied_commands();
+                    commands.get(&key).map(|(_, entry)| entry.scope)
+                })
+                .unwrap_or_default();
+            worker::remove_key(&config.applied(), &key, scope)?;
+            let removed = config.remove_command(&key);
+            config.write()?;
+            shell::generate(&config.applied())?;
+
+            let vars = match &removed {
+                Some(entry) => hooks::command_vars(&key, entry),
+                None => vec![("RHIZA_KEY", key.to_string())],
+            };
+            hooks::run(&config, Hook::PostRm, scope, &vars)?;
+        }
+        Some(("run", sub_matches)) => {
+            report::emit(&worker::run(scope_of(sub_matches).unwrap_or_default())?);
+        }
+        Some(("undo", _)) => {
+            journal::undo()?;
+        }
+        Some(("log", _)) => {
+            journal::log()?;
         }
-        Some(("run", _)) => {
-            worker::run().unwrap();
+        Some(("shell-init", sub_matches)) => {
+            let shell = sub_matches.get_one::<String>("shell").unwrap();
+            if let Some(snippet) = shell::init_snippet(shell) {
+                println!("{}", snippet);
+            }
+        }
+        Some(("doctor", sub_matches)) => {
+            doctor::run(sub_matches.get_flag("fix"))?;
         }
         Some(("clear-skipped", _)) => {
-            let mut config = installer::check().unwrap();
+            let mut config = installer::check()?;
             config.skipped.clear();
-            config.write().unwrap();
-            println!("Cleared!")
+            config.write()?;
+            say!("Cleared!")
+        }
+        Some(("profile", sub_matches)) => {
+            let mut config = installer::check()?;
+            match sub_matches.subcommand() {
+                Some(("create", create_matches)) => profile::create(
+                    &mut config,
+                    create_matches.get_one::<String>("name").unwrap(),
+                    create_matches.get_one::<String>("from").map(|s| s.as_str()),
+                )?,
+                Some(("use", use_matches)) => {
+                    let names: Vec<String> = use_matches
+                        .get_many::<String>("names")
+                        .unwrap()
+                        .cloned()
+                        .collect();
+                    profile::activate(&mut config, &names)?
+                }
+                Some(("diff", diff_matches)) => profile::diff(
+                    &config,
+                    diff_matches.get_one::<String>("from").unwrap(),
+                    diff_matches.get_one::<String>("to").unwrap(),
+                )?,
+                _ => profile::list(&config)?,
+            }
+        }
+        Some(("sync", sub_matches)) => match sub_matches.subcommand() {
+            Some(("init", init_matches)) => {
+                sync::init(init_matches.get_one::<String>("repo").unwrap())?
+            }
+            Some(("push", _)) => sync::push()?,
+            Some(("pull", _)) => sync::pull()?,
+            _ => say!("No subcommand was used. Use 'rhz sync --help' for more information."),
+        },
+        Some(("env", sub_matches)) => {
+            let mut config = installer::check()?;
+            let mut store = RegistryEnv(Scope::User);
+            let res = match sub_matches.subcommand() {
+                Some(("get", get_matches)) => {
+                    env::get(&store, get_matches.get_one::<String>("name").unwrap())
+                }
+                Some(("set", set_matches)) => env::set(
+                    &mut store,
+                    &mut config,
+                    set_matches.get_one::<String>("name").unwrap(),
+                    set_matches.get_one::<String>("value").unwrap(),
+                ),
+                Some(("unset", unset_matches)) => env::unset(
+                    &mut store,
+                    &mut config,
+                    unset_matches.get_one::<String>("name").unwrap(),
+                ),
+                Some(("export", _)) => env::export(&config),
+                Some(("diff", _)) => env::diff(&store, &config),
+                Some(("history", history_matches)) => {
+                    env::history(&store, history_matches.get_one::<String>("name").unwrap())
+                }
+                Some(("restore", restore_matches)) => env::restore(
+                    &mut store,
+                    &mut config,
+                    restore_matches.get_one::<String>("name").unwrap(),
+                    restore_matches.get_one::<String>("id").unwrap(),
+                    &path_env::confirm,
+                ),
+                Some(("apply", apply_matches)) => env::apply(
+                    &mut store,
+                    &mut config,
+                    apply_matches.get_one::<String>("file").map(|s| s.as_str()),
+                ),
+                _ => env::list(&store, &config),
+            };
+            res?;
+        }
+        Some(("completions", sub_matches)) => {
+            let shell = sub_matches.get_one::<String>("shell").unwrap();
+            if let Some(script) = completions::script(shell) {
+                print!("{}", script);
+            }
+        }
+        Some(("__complete", sub_matches)) => {
+            let current = sub_matches.get_one::<String>("current").unwrap();
+            let words: Vec<String> = sub_matches
+                .get_many::<String>("words")
+                .map(|words| words.cloned().collect())
+                .unwrap_or_default();
+            // Completing must not set anything up, a missing config just completes no names
+            let config = installer::read_config().unwrap_or_default();
+            for candidate in completions::complete(cli(), &config, &words, current) {
+                println!("{}", candidate);
+            }
         }
         _ => {
-            println!("No subcommand was used. Use --help for more information.");
+            say!("No subcommand was used. Use --help for more information.");
         }
     }
+
+    Ok(())
+}
+
+fn not_found(name: &str) -> Error {
+    Error::NotFound(format!("No command named '{}'", name))
+}
+
+fn launch_arg() -> Arg {
+    Arg::new("launch")
+        .long("launch")
+        .value_parser(["auto", "detached", "attached"])
+        .default_value("auto")
+        .help("Detached for gui apps, attached (waits for it) for console apps")
+}
+
+fn outputs_arg() -> Arg {
+    Arg::new("output")
+        .long("output")
+        .action(ArgAction::Append)
+        .value_delimiter(',')
+        .value_parser(Output::NAMES)
+        .help("Where to put it, shim and start-menu by default")
+}
+
+fn outputs_of(matches: &ArgMatches) -> Option<Vec<Output>> {
+    let outputs: Vec<Output> = matches
+        .get_many::<String>("output")?
+        .filter_map(|output| Output::parse(output))
+        .collect();
+    Some(outputs)
+}
+
+/// Runs the post-add hook once `name` is written to the config
+fn post_add(config: &Config, name: &str) -> error::Result<()> {
+    if let Some(entry) = config.current().get(name) {
+        let vars = hooks::command_vars(name, entry);
+        hooks::run(config, Hook::PostAdd, entry.scope, &vars)?;
+    }
+    Ok(())
+}
+
+/// The result of commands that change a single entry
+fn emit_entry(name: &str, entry: &Entry) {
+    report::emit(&json!({ "name": name, "entry": entry }));
+}
+
+fn category_arg() -> Arg {
+    Arg::new("category")
+        .long("category")
+        .help("Start Menu subfolder to put it in, e.g Dev or Games/Emulators")
+}
+
+fn force_arg() -> Arg {
+    Arg::new("force")
+        .long("force")
+        .action(ArgAction::SetTrue)
+        .help("Replace the link in ~\\.rhiza\\src if it already exists")
+}
+
+fn scope_arg() -> Arg {
+    Arg::new("scope")
+        .long("scope")
+        .value_parser(["user", "machine"])
+        .help("Install for the current user, or for every user (needs an elevated shell)")
+}
+
+fn scope_of(matches: &ArgMatches) -> Option<Scope> {
+    matches
+        .get_one::<String>("scope")
+        .and_then(|scope| Scope::parse(scope))
+}
+
+fn cli() -> Command {
+    Command::new("Rhiza")
+        .version("1.0")
+        .about("A blazingly fast app linker for Windows 🚀")
+        .color(ColorChoice::Always)
+        .styles(
+            Styles::styled()
+                .header(AnsiColor::Green.on_default().bold())
+                .literal(AnsiColor::Blue.on_default()),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .global(true)
+                .action(ArgAction::SetTrue)
+                .help("Print results as json on stdout, messages go to stderr"),
+        )
+        .arg(
+            Arg::new("config-dir")
+                .long("config-dir")
+                .global(true)
+                .value_name("DIR")
+                .help("Use DIR as the rhiza home instead of RHIZA_HOME or ~\\.rhiza"),
+        )
+        .subcommand(Command::new("crawl").about("Find potential apps to link"))
+        .subcommand(
+            Command::new("add")
+                .about("Search for a single app to add, or add the given file or folder")
+                .arg(
+                    Arg::new("target")
+                        .help("File or folder to add, searched for when omitted"),
+                )
+                .arg(launch_arg())
+                .arg(scope_arg())
+                .arg(category_arg())
+                .arg(outputs_arg()),
+        )
+        .subcommand(
+            Command::new("path")
+                .about("Search for a single app to add to path, or manage the user PATH")
+                .arg(scope_arg().global(true))
+                .arg(
+                    Arg::new("file")
+                        .long("file")
+                        .action(ArgAction::SetTrue)
+                        .help(
+                            "Expose only the picked executable through a shim, not its directory",
+                        ),
+                )
+                .subcommand(
+                    Command::new("list")
+                        .about("List the PATH entries, flagging missing ones and rhiza's"),
+                )
+                .subcommand(Command::new("dedupe").about("Remove repeated PATH entries"))
+                .subcommand(
+                    Command::new("prune-missing")
+                        .about("Remove PATH entries whose directory doesn't exist"),
+                )
+                .subcommand(
+                    Command::new("move")
+                        .about("Move a PATH entry before another one")
+                        .arg(
+                            Arg::new("entry")
+                                .required(true)
+                                .help("Entry to move, or its number in 'rhz path list'"),
+                        )
+                        .arg(
+                            Arg::new("before")
+                                .long("before")
+                                .required(true)
+                                .help("Entry to move it before"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("edit")
+                        .about("Edit the PATH in $VISUAL or $EDITOR, notepad by default"),
+                )
+                .subcommand(
+                    Command::new("history").about("List the PATH snapshots taken before changes"),
+                )
+                .subcommand(
+                    Command::new("restore")
+                        .about("Roll the PATH back to a snapshot")
+                        .arg(
+                            Arg::new("id")
+                                .required(true)
+                                .help("Snapshot id from 'rhz path history'"),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("url")
+                .about("Create a web or protocol shortcut (https://, steam://, vscode://...)")
+                .arg(scope_arg())
+                .arg(category_arg())
+                .arg(outputs_arg())
+                .arg(Arg::new("name").required(true).help("Name of the command"))
+                .arg(
+                    Arg::new("url")
+                        .required(true)
+                        .help("Url the shortcut opens"),
+                )
+                .arg(
+                    Arg::new("icon")
+                        .long("icon")
+                        .help("Icon file (.ico, .exe or .dll) for the shortcut"),
+                )
+                .arg(
+                    Arg::new("icon-index")
+                        .long("icon-index")
+                        .value_parser(clap::value_parser!(i32))
+                        .default_value("0")
+                        .help("Index of the icon inside the icon file"),
+                )
+                .arg(force_arg()),
+        )
+        .subcommand(
+            Command::new("lnk")
+                .about("Create a shortcut with arguments, icon, hotkey...")
+                .arg(scope_arg())
+                .arg(force_arg())
+                .arg(category_arg())
+                .arg(outputs_arg())
+                .arg(Arg::new("name").required(true).help("Name of the command"))
+                .arg(
+                    Arg::new("target")
+                        .required(true)
+                        .help("Absolute path to the target"),
+                )
+                .arg(
+                    Arg::new("args")
+                        .long("args")
+                        .help("Arguments passed to the target"),
+                )
+                .arg(Arg::new("dir").long("dir").help("Working directory"))
+                .arg(
+                    Arg::new("icon")
+                        .long("icon")
+                        .help("Icon file (.ico, .exe or .dll)"),
+                )
+                .arg(
+                    Arg::new("icon-index")
+                        .long("icon-index")
+                        .value_parser(clap::value_parser!(i32))
+                        .default_value("0")
+                        .help("Index of the icon inside the icon file"),
+                )
+                .arg(
+                    Arg::new("description")
+                        .long("description")
+                        .help("Comment shown in the shortcut's tooltip"),
+                )
+                .arg(
+                    Arg::new("hotkey")
+                        .long("hotkey")
+                        .value_parser(|hotkey: &str| {
+                            HotKey::parse(hotkey).ok_or(format!("Invalid hotkey '{}'", hotkey))
+                        })
+                        .help("Global hotkey, e.g Ctrl+Alt+K"),
+                )
+                .arg(
+                    Arg::new("show")
+                        .long("show")
+                        .value_parser(["normal", "max", "min"])
+                        .default_value("normal")
+                        .help("Window state to start the target in"),
+                )
+                .arg(
+                    Arg::new("admin")
+                        .long("admin")
+                        .action(ArgAction::SetTrue)
+                        .help("Always run the target as administrator"),
+                ),
+        )
+        .subcommand(
+            Command::new("rm")
+                .about("Removed an key added by rhiza")
+                .arg(scope_arg())
+                .arg(
+                    Arg::new("name")
+                        .value_name(completions::COMMAND_VALUE)
+                        .help("Name of the command, prompts for it when omitted"),
+                ),
+        )
+        .subcommand(
+            Command::new("run")
+                .about("Create the lnk files")
+                .arg(scope_arg()),
+        )
+        .subcommand(Command::new("view").about("View all linked apps and their config"))
+        .subcommand(Command::new("undo").about("Revert the last command that changed something"))
+        .subcommand(Command::new("log").about("Show the commands 'rhz undo' can revert"))
+        .subcommand(
+            Command::new("launch")
+                .about("Set how a command starts: detached (gui), attached (console) or auto")
+                .arg(
+                    Arg::new("name")
+                        .required(true)
+                        .value_name(completions::COMMAND_VALUE)
+                        .help("Name of the command"),
+                )
+                .arg(
+                    Arg::new("launch")
+                        .required(true)
+                        .value_parser(["auto", "detached", "attached"])
+                        .help("How to start it"),
+                ),
+        )
+        .subcommand(
+            Command::new("outputs")
+                .about("Set where a command goes: shim, start-menu, desktop, startup")
+                .arg(
+                    Arg::new("name")
+                        .required(true)
+                        .value_name(completions::COMMAND_VALUE)
+                        .help("Name of the command"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .required(true)
+                        .num_args(1..)
+                        .value_delimiter(',')
+                        .value_parser(Output::NAMES),
+                ),
+        )
+        .subcommand(
+            Command::new("category")
+                .about("Set the Start Menu subfolder of a command, or clear it")
+                .arg(
+                    Arg::new("name")
+                        .required(true)
+                        .value_name(completions::COMMAND_VALUE)
+                        .help("Name of the command"),
+                )
+                .arg(Arg::new("category").help("e.g Dev or Games/Emulators, omit to clear")),
+        )
+        .subcommand(
+            Command::new("shell-init")
+                .about("Print the line to source rhiza's shell wrappers from your profile")
+                .arg(Arg::new("shell").required(true).value_parser(shell::SHELLS)),
+        )
+        .subcommand(
+            Command::new("doctor")
+                .about("Check the rhiza setup for problems, in the machine scope too once it's used")
+                .arg(
+                    Arg::new("fix")
+                        .long("fix")
+                        .action(ArgAction::SetTrue)
+                        .help("Repair the problems that can be fixed automatically"),
+                ),
+        )
+        .subcommand(
+            Command::new("clear-skipped")
+                .about("Clear the skipped config created the the crawl command"),
+        )
+        .subcommand(
+            Command::new("profile")
+                .about("Switch between named sets of commands, e.g work and gaming")
+                .subcommand(
+                    Command::new("list").about("List the profiles, marking the active ones"),
+                )
+                .subcommand(
+                    Command::new("create")
+                        .about("Create an empty profile")
+                        .arg(Arg::new("name").required(true))
+                        .arg(
+                            Arg::new("from")
+                                .long("from")
+                                .value_name(completions::PROFILE_VALUE)
+                                .help("Start with a copy of this profile's commands"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("use")
+                        .about("Apply these profiles on 'rhz run', new commands go to the first")
+                        .arg(
+                            Arg::new("names")
+                                .required(true)
+                                .num_args(1..)
+                                .value_name(completions::PROFILE_VALUE),
+                        ),
+                )
+                .subcommand(
+                    Command::new("diff")
+                        .about("Show the commands 'to' adds, removes and changes from 'from'")
+                        .arg(
+                            Arg::new("from")
+                                .required(true)
+                                .value_name(completions::PROFILE_VALUE),
+                        )
+                        .arg(
+                            Arg::new("to")
+                                .required(true)
+                                .value_name(completions::PROFILE_VALUE),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("sync")
+                .about("Share config.json through a git repository")
+                .subcommand(
+                    Command::new("init")
+                        .about("Sync through a git working copy, or clone a repository to sync through")
+                        .arg(
+                            Arg::new("repo")
+                                .required(true)
+                                .help("A working copy, a bare repository or a url"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("push").about("Commit config.json and push it, merging first"),
+                )
+                .subcommand(
+                    Command::new("pull")
+                        .about("Merge the remote config.json key by key, then 'rhz run'"),
+                ),
+        )
+        .subcommand(
+            Command::new("env")
+                .about("Manage your user environment variables")
+                .subcommand(Command::new("list").about("List the variables, marking tracked ones"))
+                .subcommand(
+                    Command::new("get")
+                        .about("Print a variable")
+                        .arg(Arg::new("name").required(true)),
+                )
+                .subcommand(
+                    Command::new("set")
+                        .about("Set a variable and track it in the config")
+                        .arg(Arg::new("name").required(true))
+                        .arg(Arg::new("value").required(true)),
+                )
+                .subcommand(
+                    Command::new("unset")
+                        .about("Remove a variable and stop tracking it")
+                        .arg(Arg::new("name").required(true)),
+                )
+                .subcommand(Command::new("export").about("Print the tracked variables as json"))
+                .subcommand(
+                    Command::new("diff")
+                        .about("Show how the environment differs from the tracked variables"),
+                )
+                .subcommand(
+                    Command::new("apply")
+                        .about("Set the tracked variables, e.g on a new machine")
+                        .arg(Arg::new("file").help("Json from 'rhz env export' to track first")),
+                )
+                .subcommand(
+                    Command::new("history")
+                        .about("List the snapshots of a variable taken before changes")
+                        .arg(Arg::new("name").required(true)),
+                )
+                .subcommand(
+                    Command::new("restore")
+                        .about("Roll a variable back to a snapshot")
+                        .arg(Arg::new("name").required(true))
+                        .arg(
+                            Arg::new("id")
+                                .required(true)
+                                .help("Snapshot id from 'rhz env history <name>'"),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("completions")
+                .about("Print the completion script for a shell")
+                .arg(
+                    Arg::new("shell")
+                        .required(true)
+                        .value_parser(completions::SHELLS),
+                ),
+        )
+        .subcommand(
+            Command::new("__complete")
+                .hide(true)
+                .arg(
+                    Arg::new("current")
+                        .long("current")
+                        .allow_hyphen_values(true)
+                        .default_value(""),
+                )
+                .arg(
+                    Arg::new("words")
+                        .num_args(0..)
+                        .last(true)
+                        .allow_hyphen_values(true),
+                ),
+        )
 }
@@ -0,0 +1,182 @@
+use colored::Colorize;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::installer::{Config, Output, Scope};
+use crate::paths;
+
+/// Copies the links of the commands with a Desktop or Startup output there, and removes the
+/// copies rhiza left for the ones that dropped it.
+///
+/// Those folders are shared with everything else, so the copies rhiza made are recorded in
+/// `placed.json` and nothing else is ever replaced or removed.
+pub fn sync(config: &Config, scope: Scope) -> io::Result<()> {
+    let mut placed = Placed::load()?;
+    for entry in fs::read_dir(scope.src_dir())? {
+        let src = entry?.path();
+        let Some(key) = src.file_stem().map(|key| key.to_string_lossy().to_string()) else {
+            continue;
+        };
+        let command = config.commands.get(&key);
+        if command.is_none() && !config.is_inactive(&key) {
+            continue;
+        }
+
+        for (output, folder) in folders(scope) {
+            if command.is_some_and(|command| command.has(output)) {
+                placed.place(&src, Path::new(&folder))?;
+            } else {
+                placed.remove_copy(&src, Path::new(&folder))?;
+            }
+        }
+    }
+    placed.save()
+}
+
+/// Removes the Desktop and Startup copies of a link in `src`, before it's deleted
+pub fn remove(src: &Path, scope: Scope) -> io::Result<()> {
+    let mut placed = Placed::load()?;
+    for (_, folder) in folders(scope) {
+        placed.remove_copy(src, Path::new(&folder))?;
+    }
+    placed.save()
+}
+
+fn folders(scope: Scope) -> [(Output, String); 2] {
+    [
+        (Output::Desktop, scope.desktop()),
+        (Output::Startup, scope.startup()),
+    ]
+}
+
+/// The copies rhiza made in folders it shares, the Desktop, Startup and Start Menu ones.
+/// Kept next to config.json since they only make sense on this machine
+#[derive(Debug, Default)]
+pub struct Placed {
+    files: BTreeSet<PathBuf>,
+    changed: bool,
+}
+
+impl Placed {
+    pub fn load() -> io::Result<Placed> {
+        match fs::read_to_string(paths::placed_file()) {
+            Ok(content) => Ok(Placed {
+                files: serde_json::from_str(&content)?,
+                changed: false,
+            }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Placed::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+        fs::create_dir_all(paths::home())?;
+        fs::write(
+            paths::placed_file(),
+            serde_json::to_string_pretty(&self.files)?,
+        )
+    }
+
+    /// The recorded copies under `dir`
+    pub fn under<'a>(&'a self, dir: &'a Path) -> impl Iterator<Item = &'a PathBuf> + 'a {
+        self.files.iter().filter(move |file| file.starts_with(dir))
+    }
+
+    pub fn record(&mut self, path: &Path) {
+        self.changed |= self.files.insert(path.to_path_buf());
+    }
+
+    pub fn forget(&mut self, path: &Path) {
+        self.changed |= self.files.remove(path);
+    }
+
+    fn place(&mut self, src: &Path, folder: &Path) -> io::Result<()> {
+        let Some(file_name) = src.file_name() else {
+            return Ok(());
+        };
+        let dest = folder.join(file_name);
+        if fs::read(src).ok() == fs::read(&dest).ok() {
+            return Ok(());
+        }
+        if dest.exists() && !self.files.contains(&dest) {
+            let msg = format!(
+                "Skipped {}, a different file is already there",
+                dest.display()
+            );
+            say!("{}", msg.yellow());
+            return Ok(());
+        }
+
+        fs::create_dir_all(folder)?;
+        fs::copy(src, &dest)?;
+        self.record(&dest);
+        say!("{} {}", "Created".green(), dest.display());
+        Ok(())
+    }
+
+    fn remove_copy(&mut self, src: &Path, folder: &Path) -> io::Result<()> {
+        let Some(file_name) = src.file_name() else {
+            return Ok(());
+        };
+        let dest = folder.join(file_name);
+        if !self.files.remove(&dest) {
+            return Ok(());
+        }
+        self.changed = true;
+        if dest.exists() {
+            fs::remove_file(&dest)?;
+            say!("  {} {}", "Deleted".yellow(), dest.display());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_placed_copies_are_replaced_or_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src").join("app.lnk");
+        let desktop = dir.path().join("Desktop");
+        fs::create_dir_all(src.parent().unwrap()).unwrap();
+        fs::create_dir_all(&desktop).unwrap();
+        fs::write(&src, "link").unwrap();
+
+        // A file of the user's with the same name is left alone
+        let dest = desktop.join("app.lnk");
+        fs::write(&dest, "theirs").unwrap();
+        let mut placed = Placed::default();
+        placed.place(&src, &desktop).unwrap();
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "theirs");
+        placed.remove_copy(&src, &desktop).unwrap();
+        assert!(dest.exists());
+        assert!(!placed.changed);
+
+        // So is one identical to the link, it isn't rhiza's either
+        fs::write(&dest, "link").unwrap();
+        placed.place(&src, &desktop).unwrap();
+        placed.remove_copy(&src, &desktop).unwrap();
+        assert!(dest.exists());
+
+        // Copies rhiza made follow the link, even once it changes
+        fs::remove_file(&dest).unwrap();
+        placed.place(&src, &desktop).unwrap();
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "link");
+        assert!(placed.changed);
+        fs::write(&src, "new link").unwrap();
+        placed.place(&src, &desktop).unwrap();
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "new link");
+
+        fs::write(&dest, "edited").unwrap();
+        placed.remove_copy(&src, &desktop).unwrap();
+        assert!(!dest.exists());
+        assert!(placed.files.is_empty());
+    }
+}
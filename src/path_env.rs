@@ -0,0 +1,978 @@
+use colored::Colorize;
+use inquire::Confirm;
+use serde_derive::Serialize;
+use serde_json::json;
+use std::collections::HashSet;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::installer::{self, Config, PathLimits, Scope};
+use crate::paths;
+use crate::report;
+
+/// A PATH entry, with what `rhz path list` flags it as
+#[derive(Serialize)]
+pub struct PathEntry {
+    pub entry: String,
+    pub rhiza: bool,
+    pub duplicate: bool,
+    pub missing: bool,
+    /// Uses a `%VAR%` defined nowhere, so whether it exists isn't known
+    pub unresolved: bool,
+}
+
+/// Where environment variables are read from and written to, so the PATH and env commands
+/// can run against something other than the registry
+pub trait EnvStore {
+    fn get(&self, name: &str) -> io::Result<Option<String>>;
+    fn set(&mut self, name: &str, value: &str) -> io::Result<()>;
+    fn unset(&mut self, name: &str) -> io::Result<()>;
+    fn names(&self) -> io::Result<Vec<String>>;
+
+    fn read_path(&self) -> io::Result<String> {
+        Ok(self.get("Path")?.unwrap_or_default())
+    }
+
+    fn write_path(&mut self, value: &str) -> io::Result<()> {
+        self.set("Path", value)
+    }
+}
+
+/// The environment of a scope in the registry
+pub struct RegistryEnv(pub Scope);
+
+impl EnvStore for RegistryEnv {
+    fn get(&self, name: &str) -> io::Result<Option<String>> {
+        installer::read_var(self.0, name)
+    }
+
+    /// Snapshots the current value, then writes the registry and broadcasts WM_SETTINGCHANGE
+    fn set(&mut self, name: &str, value: &str) -> io::Result<()> {
+        self.0.check_elevation()?;
+        if let Some(current) = self.get(name)? {
+            snapshot(self.0, name, &current)?;
+        }
+        installer::write_var(self.0, name, value)
+    }
+
+    /// Snapshots the current value, then deletes it and broadcasts WM_SETTINGCHANGE
+    fn unset(&mut self, name: &str) -> io::Result<()> {
+        self.0.check_elevation()?;
+        match self.get(name)? {
+            Some(current) => {
+                snapshot(self.0, name, &current)?;
+                installer::delete_var(self.0, name)
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn names(&self) -> io::Result<Vec<String>> {
+        installer::var_names(self.0)
+    }
+}
+
+pub fn split(value: &str) -> Vec<String> {
+    value
+        .split(';')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| entry.to_string())
+        .collect()
+}
+
+/// The form PATH entries are compared in: expanded, with one kind of slash, no trailing one
+/// and lowercased, since paths on Windows are case-insensitive
+pub fn normalize(entry: &str) -> String {
+    expand_vars(entry.trim())
+        .replace('/', "\\")
+        .trim_end_matches('\\')
+        .to_lowercase()
+}
+
+/// Expands `%VAR%` references with the current environment, leaving unknown ones as is
+pub fn expand_vars(entry: &str) -> String {
+    expand(entry, |name| env::var(name).ok()).0
+}
+
+/// Expands `%VAR%` references with the current environment, then with the variables in
+/// `store`, which can be newer than the environment rhiza started with.
+/// None if some aren't defined in either
+pub fn resolve(store: &dyn EnvStore, entry: &str) -> Option<String> {
+    let (expanded, complete) = expand(entry, |name| {
+        env::var(name)
+            .ok()
+            .or_else(|| store.get(name).ok().flatten())
+    });
+    complete.then_some(expanded)
+}
+
+/// Whether an entry is a directory, None if it can't be told because of an unknown `%VAR%`
+fn exists(store: &dyn EnvStore, entry: &str) -> Option<bool> {
+    resolve(store, entry).map(|path| Path::new(&path).is_dir())
+}
+
+/// Expands `%VAR%` references with `lookup`, leaving unknown ones as is. Also tells if they
+/// were all known
+fn expand(entry: &str, lookup: impl Fn(&str) -> Option<String>) -> (String, bool) {
+    let mut expanded = String::new();
+    let mut complete = true;
+    let mut rest = entry;
+    while let Some(start) = rest.find('%') {
+        let Some(len) = rest[start + 1..].find('%') else {
+            break;
+        };
+        let name = &rest[start + 1..start + 1 + len];
+        expanded.push_str(&rest[..start]);
+        match lookup(name) {
+            Some(value) if !name.is_empty() => expanded.push_str(&value),
+            _ => {
+                complete = false;
+                expanded.push_str(&rest[start..start + len + 2]);
+            }
+        }
+        rest = &rest[start + len + 2..];
+    }
+    expanded.push_str(rest);
+    (expanded, complete)
+}
+
+/// Length of the PATH once its `%VAR%`s are expanded, the size Windows actually deals with
+pub fn expanded_len(value: &str) -> usize {
+    expand_vars(value).chars().count()
+}
+
+/// Refuses changes that grow the PATH past `max` and warns past `warn`, shrinking is always fine
+pub fn check_length(limits: &PathLimits, old: &str, new: &str) -> io::Result<()> {
+    let (old_len, new_len) = (expanded_len(old), expanded_len(new));
+    if new_len <= old_len || new_len <= limits.warn {
+        return Ok(());
+    }
+
+    let hint =
+        "expose single executables with 'rhz path --file', they share the one rhiza bin entry";
+    if new_len > limits.max {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "The PATH would be {} characters expanded, over the limit of {}\n{}",
+                new_len, limits.max, hint
+            ),
+        ));
+    }
+
+    say!(
+        "{}",
+        format!(
+            "The PATH will be {} characters expanded, over {}, some tools may truncate it",
+            new_len, limits.warn
+        )
+        .yellow()
+    );
+    say!("{}", format!("Tip: {}", hint).yellow());
+    Ok(())
+}
+
+pub fn contains(store: &dyn EnvStore, entry: &str) -> io::Result<bool> {
+    Ok(split(&store.read_path()?)
+        .iter()
+        .any(|candidate| normalize(candidate) == normalize(entry)))
+}
+
+/// Appends `new_path` unless it's already in the PATH
+pub fn add(store: &mut dyn EnvStore, new_path: &str) -> io::Result<()> {
+    if contains(store, new_path)? {
+        let msg = format!("'{}' is already in the PATH, skipping.", new_path).yellow();
+        say!("{}", msg);
+        return Ok(());
+    }
+
+    let current_path = store.read_path()?;
+    let new_path_value = if current_path.is_empty() || current_path.ends_with(';') {
+        format!("{}{}", current_path, new_path)
+    } else {
+        format!("{};{}", current_path, new_path)
+    };
+    write_checked(store, &current_path, &new_path_value)?;
+
+    let msg = format!("Successfully added '{}' to the PATH.", new_path).green();
+    say!("{}", msg);
+    Ok(())
+}
+
+/// The commands a directory would expose on the PATH, by name, following PATHEXT
+pub fn executables(dir: &Path) -> Vec<(String, PathBuf)> {
+    let pathext = env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string());
+    let extensions: Vec<String> = pathext
+        .split(';')
+        .map(|ext| ext.trim_start_matches('.').to_lowercase())
+        .filter(|ext| !ext.is_empty())
+        .collect();
+
+    let mut executables: Vec<(String, PathBuf)> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| extensions.contains(&ext.to_string_lossy().to_lowercase()))
+        })
+        .filter_map(|path| {
+            let name = path.file_stem()?.to_string_lossy().to_string();
+            Some((name, path))
+        })
+        .collect();
+    executables.sort_by_key(|(name, _)| name.to_lowercase());
+    executables
+}
+
+/// Where `name` already resolves on the PATH, outside of `dir`
+pub fn conflict(name: &str, dir: Option<&Path>) -> Option<PathBuf> {
+    conflict_in(name, dir, env::var_os("PATH"))
+}
+
+/// Where `name` resolves on `path`, the first entry having it wins like in a shell
+fn conflict_in(name: &str, dir: Option<&Path>, path: Option<OsString>) -> Option<PathBuf> {
+    let existing = which::which_in(name, path, env::current_dir().ok()?).ok()?;
+    let same_dir = dir.is_some_and(|dir| {
+        existing.parent().is_some_and(|parent| {
+            normalize(&parent.to_string_lossy()) == normalize(&dir.to_string_lossy())
+        })
+    });
+    (!same_dir).then_some(existing)
+}
+
+/// Prints every command adding `dir` would expose, and the names already taken on the PATH
+pub fn preview_dir(dir: &Path) {
+    let executables = executables(dir);
+    if executables.is_empty() {
+        say!(
+            "{}",
+            format!("{} has no executables", dir.display()).yellow()
+        );
+        return;
+    }
+
+    say!(
+        "{}",
+        format!(
+            "Adding {} exposes {} commands:",
+            dir.display(),
+            executables.len()
+        )
+        .purple()
+    );
+    for (name, path) in &executables {
+        let file = path.file_name().unwrap_or_default().to_string_lossy();
+        match conflict(name, Some(dir)) {
+            Some(existing) => say!(
+                "  {} ({}) {}",
+                name.bold(),
+                file,
+                format!("also {}, the first in the PATH wins", existing.display()).yellow()
+            ),
+            None => say!("  {} ({})", name.bold(), file),
+        }
+    }
+    say!(
+        "{}",
+        "Tip: 'rhz path --file' exposes only the executable you picked".blue()
+    );
+}
+
+/// Prints the PATH in order, marking missing directories and the ones rhiza manages
+pub fn list(store: &dyn EnvStore, config: &Config) -> io::Result<()> {
+    for (i, path_entry) in entries(store, config)?.iter().enumerate() {
+        let mut flags = Vec::new();
+        if path_entry.rhiza {
+            flags.push("rhiza".blue().to_string());
+        }
+        if path_entry.duplicate {
+            flags.push("duplicate".yellow().to_string());
+        }
+        if path_entry.missing {
+            flags.push("missing".red().to_string());
+        }
+        if path_entry.unresolved {
+            flags.push("unresolved".yellow().to_string());
+        }
+
+        let flags = if flags.is_empty() {
+            String::new()
+        } else {
+            format!(" [{}]", flags.join(", "))
+        };
+        say!("{:>3} {}{}", i + 1, path_entry.entry, flags);
+    }
+
+    let value = store.read_path()?;
+    let expanded = expanded_len(&value);
+    let length = format!(
+        "{} characters, {} expanded (warns over {})",
+        value.chars().count(),
+        expanded,
+        config.path_limits.warn
+    );
+    if expanded > config.path_limits.warn {
+        say!("{}", length.yellow());
+    } else {
+        say!("{}", length.blue());
+    }
+
+    Ok(())
+}
+
+/// The entries of the PATH, flagged the way `rhz path list` shows them
+pub fn entries(store: &dyn EnvStore, config: &Config) -> io::Result<Vec<PathEntry>> {
+    let managed = managed_entries(config);
+    let mut seen = HashSet::new();
+
+    let entries = split(&store.read_path()?)
+        .into_iter()
+        .map(|entry| {
+            let exists = exists(store, &entry);
+            PathEntry {
+                rhiza: managed.contains(&normalize(&entry)),
+                duplicate: !seen.insert(normalize(&entry)),
+                missing: exists == Some(false),
+                unresolved: exists.is_none(),
+                entry,
+            }
+        })
+        .collect();
+    Ok(entries)
+}
+
+/// Removes repeated entries, keeping the first one
+pub fn dedupe(store: &mut dyn EnvStore) -> io::Result<()> {
+    let entries = split(&store.read_path()?);
+    let mut seen = HashSet::new();
+    let deduped = entries
+        .iter()
+        .filter(|entry| seen.insert(normalize(entry)))
+        .cloned()
+        .collect();
+    apply(store, &entries, deduped)
+}
+
+/// Removes the entries pointing to directories that don't exist anymore. Entries using a
+/// `%VAR%` that isn't defined anywhere are kept, they may work in another session
+pub fn prune_missing(store: &mut dyn EnvStore) -> io::Result<()> {
+    let entries = split(&store.read_path()?);
+    let pruned = entries
+        .iter()
+        .filter(|entry| exists(store, entry) != Some(false))
+        .cloned()
+        .collect();
+    apply(store, &entries, pruned)
+}
+
+/// Moves `entry` right before `before`, both given as a PATH entry or as its number in `list`
+pub fn move_entry(store: &mut dyn EnvStore, entry: &str, before: &str) -> io::Result<()> {
+    let entries = split(&store.read_path()?);
+    let from = find(&entries, entry)?;
+    let to = find(&entries, before)?;
+
+    let mut moved = entries.clone();
+    let value = moved.remove(from);
+    let to = if from < to { to - 1 } else { to };
+    moved.insert(to, value);
+    apply(store, &entries, moved)
+}
+
+/// Opens the PATH in $EDITOR, one entry per line
+pub fn edit(store: &mut dyn EnvStore) -> io::Result<()> {
+    let entries = split(&store.read_path()?);
+    let file = paths::path_file();
+    fs::write(&file, entries.join("\r\n") + "\r\n")?;
+
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "notepad".to_string());
+    let mut parts = editor_command(&editor).into_iter();
+    let program = parts.next().unwrap_or_else(|| "notepad".to_string());
+    let status = process::Command::new(program)
+        .args(parts)
+        .arg(&file)
+        .status()?;
+    if !status.success() {
+        fs::remove_file(&file)?;
+        return Err(io::Error::other(format!(
+            "'{}' exited with {}, the PATH wasn't changed",
+            editor, status
+        )));
+    }
+
+    let edited = fs::read_to_string(&file)?
+        .lines()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect();
+    fs::remove_file(&file)?;
+    apply(store, &entries, edited)
+}
+
+/// The program and arguments of an `EDITOR` value. The program may be quoted, or be a path
+/// with spaces as is, like `C:\Program Files\Notepad++\notepad++.exe -multiInst`
+fn editor_command(editor: &str) -> Vec<String> {
+    let editor = editor.trim();
+    if !editor.starts_with('"') {
+        let words: Vec<&str> = editor.split_whitespace().collect();
+        for end in (2..=words.len()).rev() {
+            let program = words[..end].join(" ");
+            if Path::new(&program).is_file() {
+                let mut parts = vec![program];
+                parts.extend(words[end..].iter().map(|word| word.to_string()));
+                return parts;
+            }
+        }
+    }
+
+    // Split on spaces outside of double quotes, backslashes are path separators
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    let mut quoted = false;
+    let mut started = false;
+    for c in editor.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                started = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if started {
+                    parts.push(std::mem::take(&mut part));
+                    started = false;
+                }
+            }
+            c => {
+                part.push(c);
+                started = true;
+            }
+        }
+    }
+    if started {
+        parts.push(part);
+    }
+    parts
+}
+
+/// The entries rhiza added: its bin directories and everything added with `rhz path`
+fn managed_entries(config: &Config) -> HashSet<String> {
+    config
+        .paths
+        .iter()
+        .map(|entry| normalize(entry))
+        .chain([Scope::User, Scope::Machine].map(|scope| normalize(&scope.bin_dir())))
+        .collect()
+}
+
+fn find(entries: &[String], entry: &str) -> io::Result<usize> {
+    if let Ok(number) = entry.parse::<usize>() {
+        if (1..=entries.len()).contains(&number) {
+            return Ok(number - 1);
+        }
+    }
+    entries
+        .iter()
+        .position(|candidate| normalize(candidate) == normalize(entry))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("'{}' is not in the PATH", entry),
+            )
+        })
+}
+
+/// Saves the value of the variable `name` to `<rhiza home>/backups`, unless the latest snapshot
+/// already holds it
+pub fn snapshot(scope: Scope, name: &str, value: &str) -> io::Result<()> {
+    fs::create_dir_all(paths::backups_dir())?;
+
+    if let Some(latest) = snapshots(scope, name)?.first() {
+        if fs::read_to_string(snapshot_file(scope, name, latest))? == value {
+            return Ok(());
+        }
+    }
+
+    let id = chrono::Local::now().format("%Y%m%d-%H%M%S").to_string();
+    let mut file = snapshot_file(scope, name, &id);
+    let mut n = 1;
+    while Path::new(&file).exists() {
+        n += 1;
+        file = snapshot_file(scope, name, &format!("{}-{}", id, n));
+    }
+    fs::write(file, value)
+}
+
+/// Lists the snapshots, newest first, with how they differ from the current PATH
+pub fn history(store: &dyn EnvStore, scope: Scope) -> io::Result<()> {
+    let current = split(&store.read_path()?);
+    let ids = snapshots(scope, "Path")?;
+    if ids.is_empty() {
+        report::emit(&ids);
+        say!("{}", "No PATH snapshots yet".yellow());
+        return Ok(());
+    }
+
+    let mut history = Vec::new();
+    for id in ids {
+        let entries = split(&fs::read_to_string(snapshot_file(scope, "Path", &id))?);
+        let (removed, added) = diff(&current, &entries);
+        let taken = snapshot_time(&id);
+        history.push(json!({
+            "id": id,
+            "taken": taken,
+            "entries": entries,
+            "to_restore": { "add": added, "remove": removed },
+        }));
+        say!(
+            "{} {} {} entries {}",
+            id.bold(),
+            taken,
+            entries.len(),
+            format!("(+{} -{} to restore)", added.len(), removed.len()).blue()
+        );
+    }
+    report::emit(&history);
+    Ok(())
+}
+
+/// Shows what restoring the snapshot `id` would change and writes it back once confirmed
+pub fn restore(store: &mut dyn EnvStore, scope: Scope, id: &str) -> io::Result<()> {
+    restore_confirmed(store, scope, id, &confirm)
+}
+
+fn restore_confirmed(
+    store: &mut dyn EnvStore,
+    scope: Scope,
+    id: &str,
+    confirm: &dyn Fn(&str) -> bool,
+) -> io::Result<()> {
+    let value = read_snapshot(scope, "Path", id)?;
+    let current_value = store.read_path()?;
+    let current = split(&current_value);
+    let entries = split(&value);
+
+    if current == entries {
+        say!("{}", "The PATH already matches this snapshot".yellow());
+        return Ok(());
+    }
+    print_diff(&current, &entries);
+
+    if confirm(&format!("Restore the PATH from {}?", id)) {
+        write_checked(store, &current_value, &value)?;
+        say!("{}", format!("Restored the PATH from {}", id).green());
+    }
+    Ok(())
+}
+
+/// Asks before restoring a snapshot, no when there's no one to ask
+pub fn confirm(question: &str) -> bool {
+    Confirm::new(question)
+        .with_default(false)
+        .prompt()
+        .unwrap_or(false)
+}
+
+/// The value saved in the snapshot `id` of the variable `name`
+pub fn read_snapshot(scope: Scope, name: &str, id: &str) -> io::Result<String> {
+    if !is_snapshot_id(id) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("'{}' isn't a snapshot id, like 20240101-120000", id),
+        ));
+    }
+    let history = if name.eq_ignore_ascii_case("Path") {
+        "rhz path history".to_string()
+    } else {
+        format!("rhz env history {}", name)
+    };
+    match fs::read_to_string(snapshot_file(scope, name, id)) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No snapshot '{}', see '{}'", id, history),
+        )),
+        res => res,
+    }
+}
+
+/// When the snapshot `id` was taken, e.g `2024-01-01 12:00:00`
+pub fn snapshot_time(id: &str) -> String {
+    chrono::NaiveDateTime::parse_from_str(&id[..15], "%Y%m%d-%H%M%S")
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+/// Snapshot ids of a variable, newest first
+pub fn snapshots(scope: Scope, name: &str) -> io::Result<Vec<String>> {
+    let backups = paths::backups_dir();
+    let prefix = snapshot_prefix(scope, name);
+    let mut ids: Vec<String> = match fs::read_dir(backups) {
+        Ok(entries) => entries
+            .flatten()
+            .filter_map(|entry| {
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                let id = file_name.strip_prefix(&prefix)?.strip_suffix(".txt")?;
+                is_snapshot_id(id).then(|| id.to_string())
+            })
+            .collect(),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => return Err(err),
+    };
+    // `20240101-120000-2` sorts after `20240101-120000`, so order by the numeric suffix too
+    ids.sort_by_key(|id| {
+        let n = id[15..].trim_start_matches('-').parse::<u32>().unwrap_or(1);
+        (id[..15].to_string(), n)
+    });
+    ids.reverse();
+    Ok(ids)
+}
+
+// `20240101-120000`, with a `-2` suffix for snapshots taken in the same second
+pub fn is_snapshot_id(id: &str) -> bool {
+    let suffix = id.get(15..).unwrap_or_default();
+    id.len() >= 15
+        && id.is_ascii()
+        && id[..8].bytes().all(|b| b.is_ascii_digit())
+        && id.as_bytes()[8] == b'-'
+        && id[9..15].bytes().all(|b| b.is_ascii_digit())
+        && (suffix.is_empty()
+            || suffix
+                .strip_prefix('-')
+                .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit())))
+}
+
+// `path-<id>.txt` for the PATH, `env-<name>-<id>.txt` for other variables
+fn snapshot_prefix(scope: Scope, name: &str) -> String {
+    let prefix = if name.eq_ignore_ascii_case("Path") {
+        "path-".to_string()
+    } else {
+        format!("env-{}-", name)
+    };
+    match scope {
+        Scope::User => prefix,
+        Scope::Machine => format!("machine-{}", prefix),
+    }
+}
+
+fn snapshot_file(scope: Scope, name: &str, id: &str) -> String {
+    let file = format!("{}{}.txt", snapshot_prefix(scope, name), id);
+    paths::backups_dir()
+        .join(file)
+        .to_string_lossy()
+        .to_string()
+}
+
+/// The entries only in `old` and the ones only in `new`
+fn diff<'a>(old: &'a [String], new: &'a [String]) -> (Vec<&'a String>, Vec<&'a String>) {
+    let mut added: Vec<&String> = new.iter().collect();
+    let mut removed = Vec::new();
+    for entry in old {
+        match added.iter().position(|candidate| *candidate == entry) {
+            Some(i) => {
+                added.remove(i);
+            }
+            None => removed.push(entry),
+        }
+    }
+    (removed, added)
+}
+
+fn print_diff(old: &[String], new: &[String]) {
+    let (removed, added) = diff(old, new);
+    for entry in &removed {
+        say!("{} {}", "-".red(), entry.red());
+    }
+    for entry in &added {
+        say!("{} {}", "+".green(), entry.green());
+    }
+    if removed.is_empty() && added.is_empty() {
+        say!("{}", "Reordered the PATH".blue());
+    }
+}
+
+/// Writes `new` unless it breaks the configured length limits
+fn write_checked(store: &mut dyn EnvStore, old: &str, new: &str) -> io::Result<()> {
+    let limits = installer::read_config()
+        .map(|config| config.path_limits)
+        .unwrap_or_default();
+    check_length(&limits, old, new)?;
+    store.write_path(new)
+}
+
+/// Prints what changed and writes the new PATH, if anything did
+fn apply(store: &mut dyn EnvStore, old: &[String], new: Vec<String>) -> io::Result<()> {
+    if old == new.as_slice() {
+        say!("{}", "Nothing to change".yellow());
+        return Ok(());
+    }
+
+    print_diff(old, &new);
+    write_checked(store, &old.join(";"), &new.join(";"))?;
+    say!("{}", "Updated the PATH".green());
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Variables kept in memory, standing in for the registry. Names are case-insensitive
+    /// like there
+    #[derive(Default)]
+    pub struct MemoryEnv(pub HashMap<String, String>);
+
+    impl EnvStore for MemoryEnv {
+        fn get(&self, name: &str) -> io::Result<Option<String>> {
+            Ok(self
+                .0
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.clone()))
+        }
+
+        fn set(&mut self, name: &str, value: &str) -> io::Result<()> {
+            self.unset(name)?;
+            self.0.insert(name.to_string(), value.to_string());
+            Ok(())
+        }
+
+        fn unset(&mut self, name: &str) -> io::Result<()> {
+            self.0.retain(|key, _| !key.eq_ignore_ascii_case(name));
+            Ok(())
+        }
+
+        fn names(&self) -> io::Result<Vec<String>> {
+            Ok(self.0.keys().cloned().collect())
+        }
+    }
+
+    #[test]
+    fn prune_keeps_unresolved_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let existing = dir.path().to_string_lossy().to_string();
+        let sep = std::path::MAIN_SEPARATOR;
+        let gone = format!("{}{}gone", existing, sep);
+
+        let mut store = MemoryEnv::default();
+        store.set("RHIZA_TEST_TOOLS", &existing).unwrap();
+        let path = [
+            existing.clone(),
+            gone.clone(),
+            "%RHIZA_TEST_TOOLS%".to_string(),
+            format!("%RHIZA_TEST_TOOLS%{}gone", sep),
+            format!("%RHIZA_TEST_UNDEFINED%{}bin", sep),
+        ];
+        store.write_path(&path.join(";")).unwrap();
+
+        let flags: Vec<(bool, bool)> = entries(&store, &Config::default())
+            .unwrap()
+            .iter()
+            .map(|entry| (entry.missing, entry.unresolved))
+            .collect();
+        assert_eq!(
+            flags,
+            [
+                (false, false),
+                (true, false),
+                (false, false),
+                (true, false),
+                (false, true)
+            ]
+        );
+
+        prune_missing(&mut store).unwrap();
+        let kept = [
+            existing,
+            "%RHIZA_TEST_TOOLS%".to_string(),
+            format!("%RHIZA_TEST_UNDEFINED%{}bin", sep),
+        ];
+        assert_eq!(store.read_path().unwrap(), kept.join(";"));
+    }
+
+    #[test]
+    fn editors_may_have_spaces_in_their_path() {
+        assert_eq!(
+            editor_command("\"C:\\Program Files\\Notepad++\\notepad++.exe\" -multiInst"),
+            ["C:\\Program Files\\Notepad++\\notepad++.exe", "-multiInst"]
+        );
+        assert_eq!(editor_command("code --wait"), ["code", "--wait"]);
+        assert_eq!(editor_command(" vim "), ["vim"]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let program = dir.path().join("My Editor").join("edit.exe");
+        fs::create_dir_all(program.parent().unwrap()).unwrap();
+        fs::write(&program, "").unwrap();
+        let program = program.to_string_lossy().to_string();
+        assert_eq!(editor_command(&program), std::slice::from_ref(&program));
+        assert_eq!(
+            editor_command(&format!("{} -w", program)),
+            [program, "-w".to_string()]
+        );
+    }
+
+    #[test]
+    fn expands_known_vars_only() {
+        let mut store = MemoryEnv::default();
+        store.set("RHIZA_TEST_HOME", "C:\\Users\\me").unwrap();
+
+        assert_eq!(
+            resolve(&store, "%RHIZA_TEST_HOME%\\bin"),
+            Some("C:\\Users\\me\\bin".to_string())
+        );
+        assert_eq!(resolve(&store, "%RHIZA_TEST_NOPE%\\bin"), None);
+        assert_eq!(resolve(&store, "C:\\100%"), Some("C:\\100%".to_string()));
+        assert_eq!(
+            expand_vars("%RHIZA_TEST_NOPE%\\bin"),
+            "%RHIZA_TEST_NOPE%\\bin"
+        );
+    }
+
+    #[test]
+    fn snapshot_ids_are_a_time_and_an_optional_counter() {
+        for id in ["20240101-120000", "20240101-120000-2", "20240101-120000-12"] {
+            assert!(is_snapshot_id(id), "{}", id);
+        }
+        for id in [
+            "",
+            "latest",
+            "20240101-12000",
+            "20240101_120000",
+            "20240101-120000-",
+            "20240101-120000x",
+            "20240101-120000-a",
+            "20240101-1200000",
+            "2024010１-120000",
+            "../20240101-120000",
+        ] {
+            assert!(!is_snapshot_id(id), "{}", id);
+        }
+        assert_eq!(snapshot_time("20240101-120000-2"), "2024-01-01 12:00:00");
+    }
+
+    #[test]
+    fn snapshots_restore_the_path() {
+        let scope = Scope::User;
+        let mut store = MemoryEnv::default();
+        store.write_path("C:\\Windows;C:\\tools").unwrap();
+        snapshot(scope, "Path", &store.read_path().unwrap()).unwrap();
+        // The latest snapshot already holds it
+        snapshot(scope, "Path", &store.read_path().unwrap()).unwrap();
+        let ids = snapshots(scope, "Path").unwrap();
+        assert_eq!(ids.len(), 1);
+        let id = &ids[0];
+
+        store.write_path("C:\\Windows;C:\\other").unwrap();
+        snapshot(scope, "Path", &store.read_path().unwrap()).unwrap();
+        // Likely in the same second, the counter keeps it apart and first
+        let newer = snapshots(scope, "Path").unwrap();
+        assert_eq!(newer.len(), 2);
+        assert_eq!(&newer[1], id);
+        history(&store, scope).unwrap();
+
+        restore_confirmed(&mut store, scope, id, &|_| false).unwrap();
+        assert_eq!(store.read_path().unwrap(), "C:\\Windows;C:\\other");
+        restore_confirmed(&mut store, scope, id, &|_| true).unwrap();
+        assert_eq!(store.read_path().unwrap(), "C:\\Windows;C:\\tools");
+
+        let err = restore_confirmed(&mut store, scope, "latest", &|_| true).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = restore_confirmed(&mut store, scope, "19990101-000000", &|_| true).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert_eq!(store.read_path().unwrap(), "C:\\Windows;C:\\tools");
+    }
+
+    #[test]
+    fn path_length_is_checked_against_the_limits() {
+        let limits = PathLimits { warn: 10, max: 20 };
+        let short = "C:\\a;C:\\b";
+        let long = "C:\\a;C:\\b;C:\\c;C:\\d";
+        let too_long = "C:\\a;C:\\b;C:\\c;C:\\d;C:\\e";
+
+        // Under the warning threshold
+        check_length(&limits, "", short).unwrap();
+        // Past it, but still allowed
+        check_length(&limits, short, long).unwrap();
+
+        let err = check_length(&limits, long, too_long).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("over the limit of 20"));
+        // Shrinking an overlong PATH is always fine
+        check_length(&limits, too_long, long).unwrap();
+        check_length(&limits, too_long, too_long).unwrap();
+    }
+
+    // An empty file `name` would run as, executable on unix
+    fn command(dir: &Path, name: &str) -> PathBuf {
+        let file_name = if cfg!(windows) {
+            format!("{}.exe", name)
+        } else {
+            name.to_string()
+        };
+        let file = dir.join(file_name);
+        fs::write(&file, "").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&file, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        file
+    }
+
+    #[test]
+    fn executables_follow_pathext() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in ["b.cmd", "App.exe", "tool.BAT", "readme.txt", "noext"] {
+            fs::write(dir.path().join(file), "").unwrap();
+        }
+        fs::create_dir(dir.path().join("sub.exe")).unwrap();
+
+        let names: Vec<String> = executables(dir.path())
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, ["App", "b", "tool"]);
+        assert!(executables(&dir.path().join("gone")).is_empty());
+        preview_dir(dir.path());
+    }
+
+    #[test]
+    fn conflicts_are_the_first_match_elsewhere_on_the_path() {
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        let shadowing = command(first.path(), "rhiza-test-tool");
+        command(second.path(), "rhiza-test-tool");
+        command(second.path(), "rhiza-test-only");
+        let path = env::join_paths([first.path(), second.path()]).unwrap();
+        let conflict = |name: &str, dir: &Path| conflict_in(name, Some(dir), Some(path.clone()));
+
+        // Adding `second` exposes a tool that `first` already shadows
+        assert_eq!(
+            conflict("rhiza-test-tool", second.path()),
+            Some(shadowing.clone())
+        );
+        // `first` is where it already resolves, that's no conflict
+        assert_eq!(conflict("rhiza-test-tool", first.path()), None);
+        assert_eq!(conflict("rhiza-test-only", second.path()), None);
+        assert_eq!(conflict("rhiza-test-nope", second.path()), None);
+        assert_eq!(
+            conflict_in("rhiza-test-tool", None, Some(path.clone())),
+            Some(shadowing)
+        );
+    }
+
+    #[test]
+    fn dedupes_case_insensitively() {
+        let mut store = MemoryEnv::default();
+        store.write_path("C:\\Tools;c:/tools/;D:\\bin").unwrap();
+        dedupe(&mut store).unwrap();
+        assert_eq!(store.read_path().unwrap(), "C:\\Tools;D:\\bin");
+    }
+}
@@ -0,0 +1,127 @@
+use shellexpand::tilde;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+static HOME: OnceLock<PathBuf> = OnceLock::new();
+
+/// Resolves the rhiza home from `--config-dir`, then `RHIZA_HOME`, then `~\.rhiza`.
+/// Called once at startup, before anything touches the filesystem.
+pub fn init(config_dir: Option<&str>) {
+    let _ = HOME.set(resolve(config_dir, env::var("RHIZA_HOME").ok()));
+}
+
+/// The rhiza home for a `--config-dir` and a `RHIZA_HOME` value
+fn resolve(config_dir: Option<&str>, rhiza_home: Option<String>) -> PathBuf {
+    let dir = match (config_dir, rhiza_home) {
+        (Some(dir), _) => PathBuf::from(tilde(dir).to_string()),
+        (None, Some(dir)) if !dir.is_empty() => PathBuf::from(tilde(&dir).to_string()),
+        _ => default_home(),
+    };
+    // Shims and shortcuts embed paths under it, so it has to be absolute
+    std::path::absolute(&dir).unwrap_or(dir)
+}
+
+#[cfg(not(test))]
+fn default_home() -> PathBuf {
+    dirs::home_dir().unwrap_or_default().join(".rhiza")
+}
+
+// Tests never touch the real home
+#[cfg(test)]
+fn default_home() -> PathBuf {
+    env::temp_dir().join(format!("rhiza-test-{}", std::process::id()))
+}
+
+/// The rhiza home, holding config.json and the user scope's `src` and `bin`
+pub fn home() -> &'static Path {
+    HOME.get_or_init(default_home)
+}
+
+pub fn config_file() -> PathBuf {
+    home().join("config.json")
+}
+
+pub fn panic_log() -> PathBuf {
+    home().join("panic.log")
+}
+
+/// The per-shell wrappers written by `shell::generate`
+pub fn shell_dir() -> PathBuf {
+    home().join("shell")
+}
+
+/// Snapshots of PATH and other variables, taken before rhiza changes them
+pub fn backups_dir() -> PathBuf {
+    home().join("backups")
+}
+
+pub fn journal_dir() -> PathBuf {
+    home().join("journal")
+}
+
+/// Scratch file for `rhz path edit`
+pub fn path_file() -> PathBuf {
+    home().join("PATH.txt")
+}
+
+/// The Desktop, Startup and Start Menu copies rhiza made, the only ones it removes
+pub fn placed_file() -> PathBuf {
+    home().join("placed.json")
+}
+
+/// Where `rhz sync init` clones the config repository
+pub fn sync_dir() -> PathBuf {
+    home().join("sync")
+}
+
+/// The working copy `rhz sync` commits config.json into, kept out of the synced config
+pub fn sync_file() -> PathBuf {
+    home().join("sync.json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_dir_comes_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let other = tempfile::tempdir().unwrap();
+        let home = other.path().to_string_lossy().to_string();
+        assert_eq!(
+            resolve(Some(&dir.path().to_string_lossy()), Some(home)),
+            dir.path()
+        );
+    }
+
+    #[test]
+    fn rhiza_home_comes_next() {
+        let dir = tempfile::tempdir().unwrap();
+        let home = dir.path().to_string_lossy().to_string();
+        assert_eq!(resolve(None, Some(home)), dir.path());
+    }
+
+    #[test]
+    fn falls_back_to_the_default() {
+        assert_eq!(resolve(None, None), default_home());
+        assert_eq!(resolve(None, Some(String::new())), default_home());
+    }
+
+    #[test]
+    fn resolved_homes_are_absolute() {
+        let relative = resolve(Some("portable"), None);
+        assert!(relative.is_absolute());
+        assert!(relative.ends_with("portable"));
+
+        let tilde = resolve(Some("~/rhiza"), None);
+        assert_eq!(tilde, dirs::home_dir().unwrap().join("rhiza"));
+    }
+
+    #[test]
+    fn files_live_under_the_home() {
+        assert_eq!(config_file(), home().join("config.json"));
+        assert!(sync_dir().starts_with(home()));
+        assert_ne!(home(), dirs::home_dir().unwrap().join(".rhiza"));
+    }
+}
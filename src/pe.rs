@@ -0,0 +1,454 @@
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Subsystem {
+    Gui,
+    Console,
+    Other(u16),
+}
+
+/// Reads the subsystem field of a PE (.exe) optional header
+pub fn subsystem(path: &Path) -> io::Result<Subsystem> {
+    let mut file = fs::File::open(path)?;
+
+    let mut dos_header = [0; 64];
+    file.read_exact(&mut dos_header)?;
+    if &dos_header[..2] != b"MZ" {
+        return Err(invalid("missing MZ signature"));
+    }
+    let pe_offset = u32::from_le_bytes([
+        dos_header[0x3C],
+        dos_header[0x3D],
+        dos_header[0x3E],
+        dos_header[0x3F],
+    ]);
+
+    // PE signature, then the 20 byte COFF header, then the optional header
+    let mut signature = [0; 4];
+    file.seek(SeekFrom::Start(pe_offset as u64))?;
+    file.read_exact(&mut signature)?;
+    if &signature != b"PE\0\0" {
+        return Err(invalid("missing PE signature"));
+    }
+
+    // Subsystem sits at the same offset in PE32 and PE32+ optional headers
+    let mut subsystem = [0; 2];
+    file.seek(SeekFrom::Start(pe_offset as u64 + 4 + 20 + 68))?;
+    file.read_exact(&mut subsystem)?;
+
+    let subsystem = match u16::from_le_bytes(subsystem) {
+        2 => Subsystem::Gui,
+        3 => Subsystem::Console,
+        other => Subsystem::Other(other),
+    };
+    Ok(subsystem)
+}
+
+const RT_ICON: u32 = 3;
+const RT_GROUP_ICON: u32 = 14;
+const RESOURCE_DIRECTORY: usize = 2;
+
+/// Builds an .ico file out of an icon group in the resources of a PE file.
+///
+/// Like `IconIndex` in shortcuts, a positive `index` counts groups in resource order and a
+/// negative one is the resource id of the group. Returns `None` when there is no such group.
+pub fn icon(bytes: &[u8], index: i32) -> io::Result<Option<Vec<u8>>> {
+    let resources = match Resources::new(bytes)? {
+        Some(resources) => resources,
+        None => return Ok(None),
+    };
+    let groups = match resources.find(resources.root, RT_GROUP_ICON)? {
+        Some(groups) => resources.directory(groups)?,
+        None => return Ok(None),
+    };
+
+    let entries = resources.entries(groups)?;
+    let group = if index < 0 {
+        entries
+            .iter()
+            .find(|(id, _)| *id == index.unsigned_abs())
+            .map(|(_, offset)| *offset)
+    } else {
+        entries.get(index as usize).map(|(_, offset)| *offset)
+    };
+    let group = match group {
+        Some(group) => resources.data(group)?,
+        None => return Ok(None),
+    };
+    let icons = match resources.find(resources.root, RT_ICON)? {
+        Some(icons) => resources.directory(icons)?,
+        None => return Ok(None),
+    };
+
+    // GRPICONDIR matches the .ico header, but its entries end with a resource id instead of
+    // an offset to the image
+    let count = read_u16(group, 4)? as usize;
+    let mut header = group
+        .get(..6)
+        .ok_or_else(|| invalid("truncated icon group"))?
+        .to_vec();
+    let mut images = Vec::new();
+    let mut offset = 6 + 16 * count;
+    for i in 0..count {
+        let entry = group
+            .get(6 + 14 * i..6 + 14 * (i + 1))
+            .ok_or_else(|| invalid("truncated icon group"))?;
+        let id = read_u16(entry, 12)? as u32;
+        let image = match resources.find(icons, id)? {
+            Some(image) => resources.data(image)?,
+            None => return Err(invalid("icon group points to a missing icon")),
+        };
+
+        header.extend_from_slice(&entry[..8]);
+        header.extend_from_slice(&(image.len() as u32).to_le_bytes());
+        header.extend_from_slice(&(offset as u32).to_le_bytes());
+        images.extend_from_slice(image);
+        offset += image.len();
+    }
+
+    header.extend(images);
+    Ok(Some(header))
+}
+
+/// The resource section of a PE file
+struct Resources<'a> {
+    bytes: &'a [u8],
+    /// Offset of the root directory in the file
+    root: usize,
+    /// Virtual address, size and file offset of every section
+    sections: Vec<(u32, u32, u32)>,
+}
+
+impl<'a> Resources<'a> {
+    fn new(bytes: &'a [u8]) -> io::Result<Option<Self>> {
+        if bytes.get(..2) != Some(b"MZ") {
+            return Err(invalid("missing MZ signature"));
+        }
+        let pe_offset = read_u32(bytes, 0x3C)? as usize;
+        if bytes.get(pe_offset..add(pe_offset, 4)?) != Some(b"PE\0\0") {
+            return Err(invalid("missing PE signature"));
+        }
+
+        let coff = add(pe_offset, 4)?;
+        let section_count = read_u16(bytes, add(coff, 2)?)? as usize;
+        let optional_size = read_u16(bytes, add(coff, 16)?)? as usize;
+        let optional = add(coff, 20)?;
+
+        // Data directories start later in PE32+ headers, which have 64-bit fields
+        let directories = match read_u16(bytes, optional)? {
+            0x10B => add(optional, 96)?,
+            0x20B => add(optional, 112)?,
+            _ => return Err(invalid("unknown optional header")),
+        };
+        let resource_rva = read_u32(bytes, add(directories, RESOURCE_DIRECTORY * 8)?)?;
+        if resource_rva == 0 {
+            return Ok(None);
+        }
+
+        let mut sections = Vec::new();
+        for i in 0..section_count {
+            let header = add(add(optional, optional_size)?, 40 * i)?;
+            sections.push((
+                read_u32(bytes, add(header, 12)?)?,
+                read_u32(bytes, add(header, 8)?)?.max(read_u32(bytes, add(header, 16)?)?),
+                read_u32(bytes, add(header, 20)?)?,
+            ));
+        }
+
+        let mut resources = Resources {
+            bytes,
+            root: 0,
+            sections,
+        };
+        resources.root = resources.offset(resource_rva)?;
+        Ok(Some(resources))
+    }
+
+    fn offset(&self, rva: u32) -> io::Result<usize> {
+        let (address, _, raw) = self
+            .sections
+            .iter()
+            .find(|(address, size, _)| rva >= *address && rva - address < *size)
+            .ok_or_else(|| invalid("resource outside of every section"))?;
+        add((rva - address) as usize, *raw as usize)
+    }
+
+    /// Ids and offsets of the entries of a directory, named entries (without an id) first
+    fn entries(&self, directory: usize) -> io::Result<Vec<(u32, u32)>> {
+        let count = read_u16(self.bytes, directory + 12)? as usize
+            + read_u16(self.bytes, directory + 14)? as usize;
+        (0..count)
+            .map(|i| {
+                let entry = add(directory, 16 + 8 * i)?;
+                Ok((
+                    read_u32(self.bytes, entry)?,
+                    read_u32(self.bytes, add(entry, 4)?)?,
+                ))
+            })
+            .collect()
+    }
+
+    /// The offset of the entry with the given id
+    fn find(&self, directory: usize, id: u32) -> io::Result<Option<u32>> {
+        let entries = self.entries(directory)?;
+        Ok(entries
+            .into_iter()
+            .find(|(entry_id, _)| *entry_id == id)
+            .map(|(_, offset)| offset))
+    }
+
+    /// Where the subdirectory an entry points to is in the file
+    fn directory(&self, offset: u32) -> io::Result<usize> {
+        add(self.root, (offset & 0x7FFF_FFFF) as usize)
+    }
+
+    /// The data under an entry, taking the first language when it's a directory
+    fn data(&self, mut offset: u32) -> io::Result<&'a [u8]> {
+        while offset & 0x8000_0000 != 0 {
+            offset = match self.entries(self.directory(offset)?)?.first() {
+                Some((_, offset)) => *offset,
+                None => return Err(invalid("empty resource directory")),
+            };
+        }
+
+        let entry = add(self.root, offset as usize)?;
+        let start = self.offset(read_u32(self.bytes, entry)?)?;
+        let size = read_u32(self.bytes, add(entry, 4)?)? as usize;
+        self.bytes
+            .get(start..add(start, size)?)
+            .ok_or_else(|| invalid("truncated resource"))
+    }
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> io::Result<u16> {
+    match bytes.get(at..add(at, 2)?) {
+        Some(b) => Ok(u16::from_le_bytes([b[0], b[1]])),
+        None => Err(invalid("unexpected end of file")),
+    }
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> io::Result<u32> {
+    match bytes.get(at..add(at, 4)?) {
+        Some(b) => Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        None => Err(invalid("unexpected end of file")),
+    }
+}
+
+/// `offset + len`, the offsets come from the file so they can be anything
+fn add(offset: usize, len: usize) -> io::Result<usize> {
+    offset
+        .checked_add(len)
+        .ok_or_else(|| invalid("offset out of range"))
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    const SECTION_RVA: u32 = 0x1000;
+    const SECTION_RAW: usize = 0x200;
+    pub const IMAGE_1: &[u8] = &[0x11; 40];
+    pub const IMAGE_2: &[u8] = &[0x22; 20];
+
+    fn put(bytes: &mut Vec<u8>, at: usize, data: &[u8]) {
+        if bytes.len() < at + data.len() {
+            bytes.resize(at + data.len(), 0);
+        }
+        bytes[at..at + data.len()].copy_from_slice(data);
+    }
+
+    fn u16s(values: &[u16]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    }
+
+    fn u32s(values: &[u32]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    }
+
+    /// A resource directory with `entries` of ids and offsets
+    fn directory(entries: &[(u32, u32)]) -> Vec<u8> {
+        let mut dir = u32s(&[0, 0]);
+        dir.extend(u16s(&[0, 0, 0, entries.len() as u16]));
+        for (id, offset) in entries {
+            dir.extend(u32s(&[*id, *offset]));
+        }
+        dir
+    }
+
+    /// A GRPICONDIR with an entry per icon resource id
+    fn group(ids: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut group = u16s(&[0, 1, ids.len() as u16]);
+        for (i, (id, image)) in ids.iter().enumerate() {
+            group.extend_from_slice(&[16 * (i as u8 + 1), 16 * (i as u8 + 1), 0, 0]);
+            group.extend(u16s(&[1, 32]));
+            group.extend(u32s(&[image.len() as u32]));
+            group.extend(u16s(&[*id]));
+        }
+        group
+    }
+
+    /// A PE32 GUI executable with two icons and two icon groups: id 5 holding both icons,
+    /// under a language directory like compilers emit, and id 7 holding the second one
+    pub fn sample() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        put(&mut bytes, 0, b"MZ");
+        put(&mut bytes, 0x3C, &u32s(&[0x40]));
+        put(&mut bytes, 0x40, b"PE\0\0");
+        // COFF header: one section, a PE32 optional header of 224 bytes
+        put(&mut bytes, 0x46, &u16s(&[1]));
+        put(&mut bytes, 0x54, &u16s(&[224]));
+        let optional = 0x58;
+        put(&mut bytes, optional, &u16s(&[0x10B]));
+        put(&mut bytes, optional + 68, &u16s(&[2]));
+        put(&mut bytes, optional + 96 + 16, &u32s(&[SECTION_RVA, 0x300]));
+        // The .rsrc section header
+        let section = optional + 224;
+        put(&mut bytes, section, b".rsrc\0\0\0");
+        put(
+            &mut bytes,
+            section + 8,
+            &u32s(&[0x300, SECTION_RVA, 0x300, SECTION_RAW as u32]),
+        );
+
+        let group_5 = group(&[(1, IMAGE_1), (2, IMAGE_2)]);
+        let group_7 = group(&[(2, IMAGE_2)]);
+        let subdir = 0x8000_0000;
+        let rsrc = [
+            (
+                0x000,
+                directory(&[(RT_ICON, subdir | 0x30), (RT_GROUP_ICON, subdir | 0x60)]),
+            ),
+            (0x030, directory(&[(1, 0x100), (2, 0x110)])),
+            (0x060, directory(&[(5, subdir | 0x90), (7, 0x130)])),
+            (0x090, directory(&[(1033, 0x120)])),
+            (
+                0x100,
+                u32s(&[SECTION_RVA + 0x200, IMAGE_1.len() as u32, 0, 0]),
+            ),
+            (
+                0x110,
+                u32s(&[SECTION_RVA + 0x240, IMAGE_2.len() as u32, 0, 0]),
+            ),
+            (
+                0x120,
+                u32s(&[SECTION_RVA + 0x280, group_5.len() as u32, 0, 0]),
+            ),
+            (
+                0x130,
+                u32s(&[SECTION_RVA + 0x2C0, group_7.len() as u32, 0, 0]),
+            ),
+            (0x200, IMAGE_1.to_vec()),
+            (0x240, IMAGE_2.to_vec()),
+            (0x280, group_5),
+            (0x2C0, group_7),
+        ];
+        for (at, data) in rsrc {
+            put(&mut bytes, SECTION_RAW + at, &data);
+        }
+        bytes.resize(SECTION_RAW + 0x300, 0);
+        bytes
+    }
+
+    /// The .ico expected for the given images, sized like `group` sizes them
+    pub fn ico(images: &[&[u8]]) -> Vec<u8> {
+        let mut ico = u16s(&[0, 1, images.len() as u16]);
+        let mut offset = 6 + 16 * images.len();
+        for (i, image) in images.iter().enumerate() {
+            ico.extend_from_slice(&[16 * (i as u8 + 1), 16 * (i as u8 + 1), 0, 0]);
+            ico.extend(u16s(&[1, 32]));
+            ico.extend(u32s(&[image.len() as u32, offset as u32]));
+            offset += image.len();
+        }
+        for image in images {
+            ico.extend_from_slice(image);
+        }
+        ico
+    }
+
+    #[test]
+    fn rebuilds_icon_groups_as_ico() {
+        let bytes = sample();
+        assert_eq!(icon(&bytes, 0).unwrap(), Some(ico(&[IMAGE_1, IMAGE_2])));
+        assert_eq!(icon(&bytes, 1).unwrap(), Some(ico(&[IMAGE_2])));
+        assert_eq!(icon(&bytes, 2).unwrap(), None);
+    }
+
+    #[test]
+    fn negative_index_is_a_resource_id() {
+        let bytes = sample();
+        assert_eq!(icon(&bytes, -5).unwrap(), Some(ico(&[IMAGE_1, IMAGE_2])));
+        assert_eq!(icon(&bytes, -7).unwrap(), Some(ico(&[IMAGE_2])));
+        assert_eq!(icon(&bytes, -1).unwrap(), None);
+        assert_eq!(icon(&bytes, i32::MIN).unwrap(), None);
+    }
+
+    #[test]
+    fn truncated_files_are_errors() {
+        let bytes = sample();
+        for len in 0..bytes.len() {
+            // Cut anywhere, it fails or finds nothing, but never panics
+            let _ = icon(&bytes[..len], 0);
+        }
+        assert!(icon(&bytes[..0x100], 0).is_err());
+        assert!(icon(&bytes[..SECTION_RAW + 0x290], 0).is_err());
+        assert!(icon(b"not a PE file", 0).is_err());
+    }
+
+    #[test]
+    fn out_of_range_offsets_are_errors() {
+        let mut bytes = sample();
+        put(&mut bytes, 0x3C, &u32s(&[u32::MAX]));
+        assert!(icon(&bytes, 0).is_err());
+
+        // A section reaching past the 4 GB the rvas can address
+        let mut bytes = sample();
+        let section = 0x58 + 224;
+        put(&mut bytes, section + 8, &u32s(&[u32::MAX, u32::MAX - 0x10]));
+        assert!(icon(&bytes, 0).is_err());
+
+        // An icon pointing past the end of the file
+        let mut bytes = sample();
+        put(
+            &mut bytes,
+            SECTION_RAW + 0x100,
+            &u32s(&[SECTION_RVA + 0x200, u32::MAX]),
+        );
+        assert!(icon(&bytes, 0).is_err());
+        assert_eq!(icon(&bytes, 1).unwrap(), Some(ico(&[IMAGE_2])));
+
+        // A subdirectory offset that overflows
+        let mut bytes = sample();
+        put(&mut bytes, SECTION_RAW + 0x14, &u32s(&[0xFFFF_FFFF]));
+        assert!(icon(&bytes, 0).is_err());
+    }
+
+    #[test]
+    fn files_without_resources_have_no_icon() {
+        let mut bytes = sample();
+        put(&mut bytes, 0x58 + 96 + 16, &u32s(&[0, 0]));
+        assert_eq!(icon(&bytes, 0).unwrap(), None);
+    }
+
+    #[test]
+    fn reads_the_subsystem() {
+        let dir = tempfile::tempdir().unwrap();
+        let exe = dir.path().join("app.exe");
+        fs::write(&exe, sample()).unwrap();
+        assert_eq!(subsystem(&exe).unwrap(), Subsystem::Gui);
+
+        fs::write(&exe, b"MZ").unwrap();
+        assert!(subsystem(&exe).is_err());
+    }
+}
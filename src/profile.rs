@@ -0,0 +1,284 @@
+use colored::Colorize;
+use serde_json::json;
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+
+use crate::hooks::{self, Hook};
+use crate::installer::{Config, Entry, Profile, DEFAULT_PROFILE};
+use crate::report;
+use crate::worker;
+
+/// Prints the profiles with their number of commands, marking the active ones
+pub fn list(config: &Config) -> io::Result<()> {
+    let active = config.active_profiles();
+    let mut profiles = Vec::new();
+    for name in config.profile_names() {
+        let count = config.profile(&name).map_or(0, |commands| commands.len());
+        profiles.push(json!({
+            "name": name,
+            "commands": count,
+            "active": active.contains(&name),
+        }));
+        let flag = match active.iter().position(|active| *active == name) {
+            Some(0) => " [current]".green().to_string(),
+            Some(_) => " [active]".blue().to_string(),
+            None => String::new(),
+        };
+        let plural = if count == 1 { "" } else { "s" };
+        say!("{} ({} command{}){}", name.bold(), count, plural, flag);
+    }
+
+    report::emit(&profiles);
+    Ok(())
+}
+
+/// Creates an empty profile, or a copy of the profile `from`
+pub fn create(config: &mut Config, name: &str, from: Option<&str>) -> io::Result<()> {
+    if name.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Profile names can't be empty",
+        ));
+    }
+    if config.profile(name).is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("There's already a profile named '{}'", name),
+        ));
+    }
+    let commands = match from {
+        Some(from) => config.profile(from).ok_or_else(|| not_found(from))?.clone(),
+        None => Default::default(),
+    };
+
+    config
+        .profiles
+        .insert(name.to_string(), Profile { commands });
+    config.write()?;
+
+    say!("{} {}", "Created".green(), name.bold());
+    report::emit(&json!({ "name": name, "from": from }));
+    Ok(())
+}
+
+/// Makes `names` the active profiles, unlinking the commands that drop out or change.
+/// Only generated files go, the commands stay in their profiles. The post-rm hook runs for
+/// each of them, like for `rhz rm`
+pub fn activate(config: &mut Config, names: &[String]) -> io::Result<()> {
+    for name in names {
+        if config.profile(name).is_none() {
+            return Err(not_found(name));
+        }
+    }
+
+    let before = config.applied_commands();
+    config.active = if names == [DEFAULT_PROFILE] {
+        Vec::new()
+    } else {
+        names.to_vec()
+    };
+    let after = config.applied();
+    // Saved first, so a failure partway leaves the new profiles for 'rhz run' to finish with
+    // rather than files of the old ones gone under a config still naming them
+    config.write()?;
+
+    let mut unlinked = Vec::new();
+    let mut keys: Vec<&String> = before.keys().collect();
+    keys.sort();
+    for key in keys {
+        let (_, entry) = &before[key];
+        if after.commands.get(key) != Some(entry) {
+            worker::unlink_key(&after, key, entry)?;
+            unlinked.push(key.to_string());
+        }
+    }
+
+    for key in &unlinked {
+        let (_, entry) = &before[key];
+        hooks::run(
+            config,
+            Hook::PostRm,
+            entry.scope,
+            &hooks::command_vars(key, entry),
+        )?;
+    }
+
+    say!(
+        "{} {}",
+        "Using".green(),
+        config.active_profiles().join(", ").bold()
+    );
+    report::emit(&json!({ "active": config.active_profiles(), "unlinked": unlinked }));
+    say!("{}", "Do 'rhz run' to apply the changes".purple().bold());
+    Ok(())
+}
+
+/// Prints the commands `to` adds, removes and changes compared to `from`
+pub fn diff(config: &Config, from: &str, to: &str) -> io::Result<()> {
+    let old = config.profile(from).ok_or_else(|| not_found(from))?;
+    let new = config.profile(to).ok_or_else(|| not_found(to))?;
+
+    let mut changes = Vec::new();
+    for (key, old, new) in changes_between(old, new) {
+        match (old, new) {
+            (Some(old), Some(new)) => {
+                let detail = if old.target == new.target {
+                    "(settings differ)".yellow().to_string()
+                } else {
+                    format!("{} -> {}", old.target.red(), new.target.green())
+                };
+                say!("{} {}: {}", "~".yellow(), key.bold(), detail);
+            }
+            (Some(old), None) => say!("{} {} = {}", "-".red(), key.bold(), old.target.red()),
+            (None, Some(new)) => say!("{} {} = {}", "+".green(), key.bold(), new.target.green()),
+            (None, None) => continue,
+        }
+        changes.push(json!({ "name": key, "from": old, "to": new }));
+    }
+
+    if changes.is_empty() {
+        say!(
+            "{}",
+            format!("'{}' and '{}' are the same", from, to).green()
+        );
+    }
+    report::emit(&changes);
+    Ok(())
+}
+
+type Change<'a> = (&'a String, Option<&'a Entry>, Option<&'a Entry>);
+
+/// The commands that differ between two profiles, by name
+fn changes_between<'a>(
+    old: &'a HashMap<String, Entry>,
+    new: &'a HashMap<String, Entry>,
+) -> Vec<Change<'a>> {
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    keys.into_iter()
+        .map(|key| (key, old.get(key), new.get(key)))
+        .filter(|(_, old, new)| old != new)
+        .collect()
+}
+
+fn not_found(name: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("No profile named '{}'", name),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::installer::{self, Scope};
+    use crate::paths;
+    use std::fs;
+
+    fn commands(keys: &[&str]) -> HashMap<String, Entry> {
+        keys.iter()
+            .map(|key| (key.to_string(), Entry::new(format!("C:\\{}.exe", key))))
+            .collect()
+    }
+
+    fn config() -> Config {
+        // Every change saves the config
+        fs::create_dir_all(paths::home()).unwrap();
+        let mut config = Config {
+            commands: commands(&["code", "steam"]),
+            ..Config::default()
+        };
+        config.profiles.insert(
+            "work".to_string(),
+            Profile {
+                commands: commands(&["code", "teams"]),
+            },
+        );
+        config
+    }
+
+    #[test]
+    fn create_copies_or_starts_empty() {
+        let mut config = config();
+        create(&mut config, "games", None).unwrap();
+        assert!(config.profile("games").unwrap().is_empty());
+
+        create(&mut config, "laptop", Some("work")).unwrap();
+        assert_eq!(config.profile("laptop"), config.profile("work"));
+        create(&mut config, "copy", Some(DEFAULT_PROFILE)).unwrap();
+        assert_eq!(config.profile("copy"), Some(&config.commands));
+
+        let err = create(&mut config, "work", None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        let err = create(&mut config, DEFAULT_PROFILE, None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        let err = create(&mut config, "", None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = create(&mut config, "home", Some("nope")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(config.profile("home").is_none());
+    }
+
+    #[test]
+    fn activate_switches_profiles() {
+        let mut config = config();
+        activate(
+            &mut config,
+            &["work".to_string(), DEFAULT_PROFILE.to_string()],
+        )
+        .unwrap();
+        assert_eq!(config.active_profiles(), ["work", DEFAULT_PROFILE]);
+        let applied = config.applied();
+        assert!(applied.commands.contains_key("teams"));
+        assert!(applied.commands.contains_key("steam"));
+
+        // The default profile alone is stored as no active profile at all
+        activate(&mut config, &[DEFAULT_PROFILE.to_string()]).unwrap();
+        assert!(config.active.is_empty());
+
+        let err = activate(&mut config, &["work".to_string(), "nope".to_string()]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(config.active.is_empty());
+    }
+
+    #[test]
+    fn activate_keeps_the_new_profiles_when_unlinking_fails() {
+        let mut config = config();
+        // Unlinking a machine command needs an elevated shell
+        config.commands.get_mut("steam").unwrap().scope = Scope::Machine;
+        if installer::is_elevated() {
+            return;
+        }
+
+        let err = activate(&mut config, &["work".to_string()]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(config.active, ["work"]);
+    }
+
+    #[test]
+    fn diff_lists_added_removed_and_changed_commands() {
+        let config = config();
+        let old = &config.commands;
+        let new = &config.profiles["work"].commands;
+        let keys: Vec<(&str, bool, bool)> = changes_between(old, new)
+            .into_iter()
+            .map(|(key, old, new)| (key.as_str(), old.is_some(), new.is_some()))
+            .collect();
+        assert_eq!(keys, [("steam", true, false), ("teams", false, true)]);
+
+        let mut changed = new.clone();
+        changed.get_mut("code").unwrap().target = "D:\\code.exe".to_string();
+        let keys: Vec<&String> = changes_between(new, &changed)
+            .into_iter()
+            .map(|(key, _, _)| key)
+            .collect();
+        assert_eq!(keys, ["code"]);
+        assert!(changes_between(old, old).is_empty());
+
+        diff(&config, DEFAULT_PROFILE, "work").unwrap();
+
+        let err = diff(&config, "work", "nope").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        let err = diff(&config, "nope", "work").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,62 @@
+use colored::Colorize;
+use serde::Serialize;
+use serde_json::json;
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+static JSON: AtomicBool = AtomicBool::new(false);
+static EMITTED: AtomicBool = AtomicBool::new(false);
+static ERRORS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Prints a message for people, on stderr with `--json` so stdout only carries json
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if $crate::report::is_json() {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+
+pub fn set_json(json: bool) {
+    JSON.store(json, Ordering::Relaxed);
+}
+
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// Prints the result of a command as json, only with `--json`
+pub fn emit<T: Serialize>(value: &T) {
+    if !is_json() {
+        return;
+    }
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => println!("{}", json),
+        Err(err) => error(err),
+    }
+    EMITTED.store(true, Ordering::Relaxed);
+}
+
+/// Prints an error, and keeps it for the json result of commands that don't emit their own
+pub fn error(err: impl Display) {
+    eprintln!("{}", err.to_string().red());
+    if let Ok(mut errors) = ERRORS.lock() {
+        errors.push(err.to_string());
+    }
+}
+
+/// With `--json`, emits `{ "ok": ..., "code": ..., "errors": [...] }` if the command didn't
+/// emit a result
+pub fn finish(code: i32) {
+    if !is_json() || EMITTED.load(Ordering::Relaxed) {
+        return;
+    }
+    let errors = ERRORS
+        .lock()
+        .map(|errors| errors.clone())
+        .unwrap_or_default();
+    emit(&json!({ "ok": code == 0 && errors.is_empty(), "code": code, "errors": errors }));
+}
@@ -1,3 +1,4 @@
+use crate::error::{Error, Result};
 use ignore::WalkBuilder;
 use indicatif::{ProgressBar, ProgressStyle};
 use inquire::Select;
@@ -68,7 +69,7 @@ fn search_fuzzy(exts: Vec<String>, silent: bool) -> Receiver<String> {
             Box::new(move |result| {
                 if !silent {
                     let files_so_far = file_count.load(Ordering::Relaxed);
-                    if files_so_far % 1000 == 0 {
+                    if files_so_far.is_multiple_of(1000) {
                         progress_bar
                             .set_message(format!("Collecting files - Found {}", files_so_far));
                     }
@@ -76,7 +77,7 @@ fn search_fuzzy(exts: Vec<String>, silent: bool) -> Receiver<String> {
 
                 if let Ok(entry) = result {
                     // Only process files (not directories)
-                    if entry.file_type().map_or(false, |ft| ft.is_file()) {
+                    if entry.file_type().is_some_and(|ft| ft.is_file()) {
                         file_count.fetch_add(1, Ordering::Relaxed);
                         let path: PathBuf = entry.path().to_path_buf();
                         if let Some(ext) = path.extension() {
@@ -102,7 +103,8 @@ fn search_fuzzy(exts: Vec<String>, silent: bool) -> Receiver<String> {
     receiver
 }
 
-pub fn prompt_fzf(items: Receiver<String>, prompt: &str) -> Option<String> {
+/// The picked item, `Error::Cancelled` when fzf or the prompt is closed without one
+pub fn prompt_fzf(items: Receiver<String>, prompt: &str) -> Result<String> {
     // attempt fzf first
     if let Some(mut child) = spawn_fzf() {
         if let Some(stdin) = child.stdin.as_mut() {
@@ -115,28 +117,21 @@ pub fn prompt_fzf(items: Receiver<String>, prompt: &str) -> Option<String> {
         }
 
         // Wait for fzf to complete and capture its output.
-        let output = child
-            .wait_with_output()
-            .expect("Failed to wait on fzf child");
+        let output = child.wait_with_output()?;
         if output.status.success() {
-            return Some(String::from_utf8_lossy(&output.stdout).trim().to_string());
+            return Ok(String::from_utf8_lossy(&output.stdout).trim().to_string());
         } else {
-            return None;
+            return Err(Error::Cancelled);
         }
     }
 
     // Fallback
     let opts: Vec<String> = items.into_iter().collect();
 
-    Some(
-        Select::new(prompt, opts)
-            .with_vim_mode(true)
-            .prompt()
-            .unwrap(),
-    )
+    Ok(Select::new(prompt, opts).with_vim_mode(true).prompt()?)
 }
 
-pub fn search_prompt_fzf(prompt: &str, exts: Vec<String>) -> Option<String> {
+pub fn search_prompt_fzf(prompt: &str, exts: Vec<String>) -> Result<String> {
     let items = search_fuzzy(exts, which::which("fzf").is_ok());
     prompt_fzf(items, prompt)
 }
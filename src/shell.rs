@@ -0,0 +1,257 @@
+use colored::Colorize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::installer::{Config, Scope};
+use crate::paths;
+
+pub const SHELLS: [&str; 7] = ["powershell", "pwsh", "bash", "zsh", "sh", "fish", "nu"];
+
+/// Writes `<rhiza home>/shell/rhiza.{ps1,sh,fish,nu}`, defining a function per command that
+/// calls its launcher in `<rhiza home>/bin` directly, so no cmd.exe is spawned on the way.
+pub fn generate(config: &Config) -> io::Result<()> {
+    let rhiza_bin = Scope::User.bin_dir();
+    let rhiza_shell = paths::shell_dir();
+    fs::create_dir_all(&rhiza_shell)?;
+
+    let mut keys: Vec<&String> = config.commands.keys().collect();
+    keys.sort();
+
+    let mut launchers = Vec::new();
+    for key in keys {
+        if !is_valid_name(key) {
+            say!(
+                "{}",
+                format!(
+                    "'{}' isn't a valid function name, skipping shell wrappers",
+                    key
+                )
+                .yellow()
+            );
+            continue;
+        }
+
+        let bin = Path::new(&rhiza_bin);
+        let launcher = ["exe", "bat"]
+            .iter()
+            .map(|ext| bin.join(format!("{}.{}", key, ext)))
+            .find(|launcher| launcher.exists());
+        if let Some(launcher) = launcher {
+            launchers.push((key.as_str(), launcher));
+        }
+    }
+
+    for (ext, script) in scripts(&rhiza_bin, &launchers) {
+        fs::write(rhiza_shell.join(format!("rhiza.{}", ext)), script)?;
+    }
+    say!("{}", "Done writing shell wrappers".purple());
+
+    Ok(())
+}
+
+/// The wrapper scripts by extension, for the `.exe` or `.bat` launcher of each command
+fn scripts(rhiza_bin: &str, launchers: &[(&str, PathBuf)]) -> [(&'static str, String); 4] {
+    let mut ps1 = String::from("# Generated by rhiza, do not edit\r\n");
+    let mut sh = format!(
+        "# Generated by rhiza, do not edit\n\
+         _rhiza_path() {{\n  \
+           if command -v cygpath >/dev/null 2>&1; then cygpath -u \"$1\"\n  \
+           elif command -v wslpath >/dev/null 2>&1; then wslpath -u \"$1\"\n  \
+           else printf '%s\\n' \"$1\"; fi\n\
+         }}\n\
+         _rhiza_bin=\"$(_rhiza_path {})\"\n",
+        sh_quote(rhiza_bin)
+    );
+    let mut fish = format!(
+        "# Generated by rhiza, do not edit\n\
+         function _rhiza_path\n    \
+           if type -q cygpath; cygpath -u $argv[1]\n    \
+           else if type -q wslpath; wslpath -u $argv[1]\n    \
+           else; echo $argv[1]; end\n\
+         end\n\
+         set -g _rhiza_bin (_rhiza_path {})\n",
+        fish_quote(rhiza_bin)
+    );
+    // Nushell runs natively on Windows, it takes the launcher's path as is
+    let mut nu = String::from("# Generated by rhiza, do not edit\n");
+
+    for (key, launcher) in launchers {
+        let is_bat = launcher
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("bat"));
+        let launcher = launcher.to_string_lossy();
+        ps1.push_str(&format!(
+            "function global:{} {{ & {} @args }}\r\n",
+            key,
+            ps_quote(&launcher)
+        ));
+        nu.push_str(&format!(
+            "def --wrapped {} [...args] {{ ^{} ...$args }}\n",
+            key,
+            nu_quote(&launcher)
+        ));
+        if is_bat {
+            // cmd.exe doesn't understand unix paths, and git bash would mangle `/c`
+            sh.push_str(&format!(
+                "{}() {{ MSYS_NO_PATHCONV=1 cmd.exe /c {} \"$@\"; }}\n",
+                key,
+                sh_quote(&launcher)
+            ));
+            fish.push_str(&format!(
+                "function {}; MSYS_NO_PATHCONV=1 cmd.exe /c {} $argv; end\n",
+                key,
+                fish_quote(&launcher)
+            ));
+        } else {
+            sh.push_str(&format!(
+                "{}() {{ \"$_rhiza_bin/{}.exe\" \"$@\"; }}\n",
+                key, key
+            ));
+            fish.push_str(&format!(
+                "function {}; \"$_rhiza_bin/{}.exe\" $argv; end\n",
+                key, key
+            ));
+        }
+    }
+
+    [("ps1", ps1), ("sh", sh), ("fish", fish), ("nu", nu)]
+}
+
+/// The line to source the generated wrappers from a shell profile
+pub fn init_snippet(shell: &str) -> Option<String> {
+    let rhiza_shell = paths::shell_dir().to_string_lossy().to_string();
+    let file = |ext: &str| format!("{}\\rhiza.{}", rhiza_shell, ext);
+
+    let snippet = match shell {
+        "powershell" | "pwsh" => format!(". {}", ps_quote(&file("ps1"))),
+        "bash" | "zsh" | "sh" => format!(
+            ". \"$(cygpath -u {0} 2>/dev/null || wslpath -u {0})\"",
+            sh_quote(&file("sh"))
+        ),
+        "fish" => format!(
+            "source (cygpath -u {0} 2>/dev/null; or wslpath -u {0})",
+            fish_quote(&file("fish"))
+        ),
+        "nu" => format!("source {}", nu_quote(&file("nu"))),
+        _ => return None,
+    };
+    Some(snippet)
+}
+
+fn is_valid_name(key: &str) -> bool {
+    !key.is_empty()
+        && !key.starts_with('-')
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn ps_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+fn sh_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+fn fish_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn nu_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_must_be_function_names() {
+        for key in ["code", "my_app-2", "7z"] {
+            assert!(is_valid_name(key), "{}", key);
+        }
+        for key in ["", "-x", "my app", "it's", "a.b", "a\"b", "naïve", "a;rm"] {
+            assert!(!is_valid_name(key), "{}", key);
+        }
+    }
+
+    #[test]
+    fn quotes_are_escaped_per_shell() {
+        let path = "C:\\Program Files\\it's \"here\"";
+        assert_eq!(ps_quote(path), "'C:\\Program Files\\it''s \"here\"'");
+        assert_eq!(sh_quote(path), "'C:\\Program Files\\it'\\''s \"here\"'");
+        assert_eq!(fish_quote(path), "'C:\\\\Program Files\\\\it\\'s \"here\"'");
+        assert_eq!(
+            nu_quote(path),
+            "\"C:\\\\Program Files\\\\it's \\\"here\\\"\""
+        );
+    }
+
+    #[test]
+    fn init_snippets_source_the_wrappers() {
+        for shell in SHELLS {
+            assert!(init_snippet(shell).is_some(), "{}", shell);
+        }
+        assert!(init_snippet("cmd").is_none());
+
+        let snippet = init_snippet("nu").unwrap();
+        assert!(snippet.starts_with("source \""));
+        assert!(snippet.ends_with("rhiza.nu\""));
+        assert!(init_snippet("pwsh").unwrap().ends_with("rhiza.ps1'"));
+        assert!(init_snippet("zsh").unwrap().contains("rhiza.sh'"));
+        assert!(init_snippet("fish").unwrap().contains("rhiza.fish'"));
+    }
+
+    #[test]
+    fn scripts_call_the_launchers() {
+        let bin = "C:\\Users\\it's me\\.rhiza\\bin";
+        let launchers = [
+            ("code", Path::new(bin).join("code.exe")),
+            ("build", Path::new(bin).join("build.BAT")),
+        ];
+        let code = launchers[0].1.to_string_lossy().to_string();
+        let build = launchers[1].1.to_string_lossy().to_string();
+        let scripts = scripts(bin, &launchers);
+        let script = |ext: &str| {
+            let (_, script) = scripts.iter().find(|(e, _)| *e == ext).unwrap();
+            script.lines().skip(1).collect::<Vec<&str>>().join("\n")
+        };
+
+        assert_eq!(
+            script("ps1"),
+            format!(
+                "function global:code {{ & {} @args }}\nfunction global:build {{ & {} @args }}",
+                ps_quote(&code),
+                ps_quote(&build)
+            )
+        );
+        assert_eq!(
+            script("nu"),
+            format!(
+                "def --wrapped code [...args] {{ ^{} ...$args }}\n\
+                 def --wrapped build [...args] {{ ^{} ...$args }}",
+                nu_quote(&code),
+                nu_quote(&build)
+            )
+        );
+
+        let sh = script("sh");
+        assert!(sh.contains(&format!("_rhiza_bin=\"$(_rhiza_path {})\"", sh_quote(bin))));
+        assert!(sh.contains("code() { \"$_rhiza_bin/code.exe\" \"$@\"; }"));
+        // Batch files go through cmd.exe, with their Windows path
+        assert!(sh.contains(&format!(
+            "build() {{ MSYS_NO_PATHCONV=1 cmd.exe /c {} \"$@\"; }}",
+            sh_quote(&build)
+        )));
+
+        let fish = script("fish");
+        assert!(fish.contains(&format!("(_rhiza_path {})", fish_quote(bin))));
+        assert!(fish.contains("function code; \"$_rhiza_bin/code.exe\" $argv; end"));
+        assert!(fish.contains(&format!(
+            "function build; MSYS_NO_PATHCONV=1 cmd.exe /c {} $argv; end",
+            fish_quote(&build)
+        )));
+    }
+}
@@ -0,0 +1,246 @@
+use lnk_parser::LNKParser;
+use std::env;
+use std::path::{Path, PathBuf};
+
+use crate::installer::{Entry, LaunchMode};
+use crate::path_env;
+pub use crate::shim_file::Shim;
+use crate::target::{Launcher, TargetKind};
+use crate::url_file::UrlFile;
+
+impl Shim {
+    /// Builds the shim for a file in `~/.rhiza/src`, given the command it was linked from.
+    ///
+    /// Targets that can be started directly (exes and scripts) are, so stdio and exit codes
+    /// make it through. So are the targets of shortcuts, with the shortcut's arguments and
+    /// working directory, and urls go to their handler. Everything else is opened through
+    /// `start`, like the bat files did.
+    pub fn for_entry(src_file: &Path, entry: Option<&Entry>) -> Shim {
+        let launch = entry.map_or(LaunchMode::Detached, |entry| {
+            entry.launch.resolve(&entry.target)
+        });
+        let wait = launch == LaunchMode::Attached;
+
+        if let Some(source) = entry.map(|entry| Path::new(&entry.target)) {
+            if let Some((program, args)) = direct(source) {
+                return Shim {
+                    target: program.to_string_lossy().to_string(),
+                    args,
+                    dir: None,
+                    wait,
+                };
+            }
+        }
+
+        let extension = src_file
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase());
+        let shim = match extension.as_deref() {
+            Some("lnk") => Shim::for_link(src_file, wait),
+            Some("url") => Shim::for_url(src_file),
+            _ => None,
+        };
+        if let Some(shim) = shim {
+            return shim;
+        }
+
+        let start = if wait { "start /WAIT" } else { "start" };
+        Shim {
+            target: env::var("ComSpec").unwrap_or_else(|_| "C:\\Windows\\System32\\cmd.exe".into()),
+            args: Some(format!("/c {} \"\" \"{}\"", start, src_file.display())),
+            dir: None,
+            wait,
+        }
+    }
+
+    /// Starts what a shortcut points to, when it can be started directly
+    fn for_link(link: &Path, wait: bool) -> Option<Shim> {
+        let link = LNKParser::from_path(&link.to_string_lossy()).ok()?;
+        let target = path_env::expand_vars(link.get_target_full_path().as_deref()?);
+        let (program, program_args) = direct(Path::new(&target))?;
+
+        let link_args = link
+            .get_command_line_arguments()
+            .as_ref()
+            .map(|args| args.string.trim().to_string())
+            .filter(|args| !args.is_empty());
+        let args = match (program_args, link_args) {
+            (Some(program_args), Some(link_args)) => {
+                Some(format!("{} {}", program_args, link_args))
+            }
+            (program_args, link_args) => program_args.or(link_args),
+        };
+        let dir = link
+            .get_working_dir()
+            .as_ref()
+            .map(|dir| path_env::expand_vars(&dir.string))
+            .filter(|dir| !dir.is_empty());
+
+        Some(Shim {
+            target: program.to_string_lossy().to_string(),
+            args,
+            dir,
+            wait,
+        })
+    }
+
+    /// Hands the url of a .url file to its protocol handler, the way the shell opens them
+    fn for_url(file: &Path) -> Option<Shim> {
+        let url = UrlFile::read(file).ok()?.url()?.to_string();
+        let system_root = env::var("SystemRoot").unwrap_or_else(|_| "C:\\Windows".into());
+        Some(Shim {
+            target: format!("{}\\System32\\rundll32.exe", system_root),
+            args: Some(format!("url.dll,FileProtocolHandler {}", url)),
+            dir: None,
+            wait: false,
+        })
+    }
+
+    /// The same launch as a bat file, for when rhz-shim.exe is missing
+    pub fn render_bat(&self) -> String {
+        // Shims pass everything as is, a bat file would expand `%`s
+        let escape = |value: &str| value.replace('%', "%%");
+        let dir = self
+            .dir
+            .as_ref()
+            .map(|dir| format!("/D \"{}\" ", escape(dir)))
+            .unwrap_or_default();
+        let args = self
+            .args
+            .as_ref()
+            .map(|args| format!(" {}", escape(args)))
+            .unwrap_or_default();
+        if self.wait {
+            format!(
+                "@echo off\r\nstart \"\" {}/B /WAIT \"{}\"{} %*\r\nexit /b %errorlevel%\r\n",
+                dir,
+                escape(&self.target),
+                args
+            )
+        } else {
+            format!(
+                "@echo off\r\nstart \"\" {}/B \"{}\"{} %*\r\n",
+                dir,
+                escape(&self.target),
+                args
+            )
+        }
+    }
+}
+
+/// The program and arguments starting `path` without the shell: itself for executables,
+/// an interpreter for scripts and folders
+fn direct(path: &Path) -> Option<(PathBuf, Option<String>)> {
+    match TargetKind::detect(path)?.launcher(path) {
+        Ok(Launcher::Shortcut { program, args }) => Some((program, args)),
+        _ => None,
+    }
+}
+
+/// The `rhz-shim.exe` installed next to `rhz.exe`
+pub fn shim_exe() -> Option<PathBuf> {
+    let exe = env::current_exe().ok()?;
+    let shim = exe.with_file_name(format!("rhz-shim{}", env::consts::EXE_SUFFIX));
+    shim.exists().then_some(shim)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lnk::ShellLink;
+    use std::fs;
+
+    #[test]
+    fn parse_reads_what_render_writes() {
+        let shim = Shim {
+            target: "C:\\tools\\foo.exe".to_string(),
+            args: Some("--color=always".to_string()),
+            dir: Some("C:\\tools".to_string()),
+            wait: false,
+        };
+        let parsed = Shim::parse(&shim.render()).unwrap();
+        assert_eq!(parsed.target, shim.target);
+        assert_eq!(parsed.args, shim.args);
+        assert_eq!(parsed.dir, shim.dir);
+        assert!(!parsed.wait);
+
+        let old = Shim::parse("path = C:\\foo.exe\nargs =\n").unwrap();
+        assert_eq!(old.target, "C:\\foo.exe");
+        assert_eq!(old.args, None);
+        assert!(old.wait);
+        assert!(Shim::parse("args = -v").is_none());
+    }
+
+    #[test]
+    fn links_start_their_target_directly() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("build.bat");
+        fs::write(&script, "@echo off").unwrap();
+        let link_path = dir.path().join("build.lnk");
+        let mut link = ShellLink::new(&script);
+        link.arguments = Some("--release".to_string());
+        link.working_dir = Some(dir.path().to_string_lossy().to_string());
+        link.write(&link_path).unwrap();
+
+        let entry = Entry::new(link_path.to_string_lossy().to_string());
+        let shim = Shim::for_entry(&link_path, Some(&entry));
+        assert_eq!(shim.target, script.to_string_lossy());
+        assert_eq!(shim.args.as_deref(), Some("--release"));
+        assert_eq!(shim.dir.as_deref(), Some(&*dir.path().to_string_lossy()));
+        // Batch files are console programs, so the shim waits for them
+        assert!(shim.wait);
+    }
+
+    #[test]
+    fn links_to_documents_open_through_start() {
+        let dir = tempfile::tempdir().unwrap();
+        let notes = dir.path().join("notes.txt");
+        fs::write(&notes, "hello").unwrap();
+        let link_path = dir.path().join("notes.lnk");
+        ShellLink::new(&notes).write(&link_path).unwrap();
+
+        let shim = Shim::for_entry(&link_path, None);
+        let args = shim.args.unwrap();
+        assert!(args.starts_with("/c start \"\""));
+        assert!(args.contains(&*link_path.to_string_lossy()));
+    }
+
+    #[test]
+    fn urls_go_to_their_handler() {
+        let dir = tempfile::tempdir().unwrap();
+        let url_path = dir.path().join("docs.url");
+        UrlFile::new("https://example.com/a&b")
+            .write(&url_path)
+            .unwrap();
+
+        let shim = Shim::for_entry(&url_path, None);
+        assert!(shim.target.ends_with("\\System32\\rundll32.exe"));
+        assert_eq!(
+            shim.args.as_deref(),
+            Some("url.dll,FileProtocolHandler https://example.com/a&b")
+        );
+        assert!(!shim.wait);
+    }
+
+    #[test]
+    fn bat_files_launch_like_shims() {
+        let mut shim = Shim {
+            target: "C:\\tools\\foo.exe".to_string(),
+            args: Some("-v".to_string()),
+            dir: Some("C:\\tools".to_string()),
+            wait: true,
+        };
+        assert_eq!(
+            shim.render_bat(),
+            "@echo off\r\nstart \"\" /D \"C:\\tools\" /B /WAIT \"C:\\tools\\foo.exe\" -v %*\r\nexit /b %errorlevel%\r\n"
+        );
+
+        shim.wait = false;
+        shim.dir = None;
+        shim.args = Some("--name=100%".to_string());
+        assert_eq!(
+            shim.render_bat(),
+            "@echo off\r\nstart \"\" /B \"C:\\tools\\foo.exe\" --name=100%% %*\r\n"
+        );
+    }
+}
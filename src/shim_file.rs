@@ -0,0 +1,58 @@
+//! The `<name>.shim` sidecar next to every shim in `bin`, written by `rhz run` and read by
+//! `rhz-shim` (see src/bin/rhz-shim.rs), which includes this file as is.
+//!
+//! ```text
+//! target = C:\tools\foo\foo.exe
+//! args = --color=always
+//! dir = C:\tools\foo
+//! wait = true
+//! ```
+
+pub struct Shim {
+    pub target: String,
+    pub args: Option<String>,
+    pub dir: Option<String>,
+    pub wait: bool,
+}
+
+impl Shim {
+    pub fn parse(content: &str) -> Option<Shim> {
+        let mut target = None;
+        let mut args = None;
+        let mut dir = None;
+        let mut wait = true;
+
+        for line in content.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim().to_string();
+            match key.trim() {
+                "target" | "path" => target = Some(value),
+                "args" if !value.is_empty() => args = Some(value),
+                "dir" if !value.is_empty() => dir = Some(value),
+                "wait" => wait = value != "false",
+                _ => {}
+            }
+        }
+
+        target.map(|target| Shim {
+            target,
+            args,
+            dir,
+            wait,
+        })
+    }
+
+    pub fn render(&self) -> String {
+        let mut content = format!("target = {}\r\n", self.target);
+        if let Some(args) = &self.args {
+            content.push_str(&format!("args = {}\r\n", args));
+        }
+        if let Some(dir) = &self.dir {
+            content.push_str(&format!("dir = {}\r\n", dir));
+        }
+        content.push_str(&format!("wait = {}\r\n", self.wait));
+        content
+    }
+}
@@ -0,0 +1,286 @@
+use colored::Colorize;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::installer::{Config, Output, Scope};
+use crate::outputs::Placed;
+
+/// What syncing the Start Menu would do
+pub struct Changes {
+    /// Files from `src` that are missing or differ, with where they go
+    pub copy: Vec<(PathBuf, PathBuf)>,
+    /// Files rhiza placed in its folder that no command links to anymore
+    pub stale: Vec<PathBuf>,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.copy.is_empty() && self.stale.is_empty()
+    }
+}
+
+/// Where every file in `src_dir` goes under `root`
+fn plan_dir(config: &Config, src_dir: &Path, root: &Path) -> io::Result<Vec<(PathBuf, PathBuf)>> {
+    let mut files = Vec::new();
+
+    for entry in fs::read_dir(src_dir)? {
+        let src = entry?.path();
+        if !src.is_file() {
+            continue;
+        }
+        let (Some(key), Some(file_name)) = (src.file_stem(), src.file_name()) else {
+            continue;
+        };
+
+        let key = key.to_string_lossy();
+        let entry = config.commands.get(key.as_ref());
+        if entry.is_some_and(|entry| !entry.has(Output::StartMenu)) || config.is_inactive(&key) {
+            continue;
+        }
+        let folder = entry
+            .and_then(|entry| entry.category.as_deref())
+            .map(category_dir)
+            .unwrap_or_default();
+        let dest = root.join(folder).join(file_name);
+        files.push((src, dest));
+    }
+
+    Ok(files)
+}
+
+pub fn changes(config: &Config, scope: Scope) -> io::Result<Changes> {
+    changes_dir(
+        config,
+        Path::new(&scope.src_dir()),
+        &config.start_menu_root(scope)?,
+        &Placed::load()?,
+    )
+}
+
+fn changes_dir(
+    config: &Config,
+    src_dir: &Path,
+    root: &Path,
+    placed: &Placed,
+) -> io::Result<Changes> {
+    let plan = plan_dir(config, src_dir, root)?;
+    // The Start Menu is case-insensitive, a `dev` folder on disk is the `Dev` category
+    let wanted: HashSet<String> = plan.iter().map(|(_, dest)| key(dest)).collect();
+
+    let copy = plan
+        .into_iter()
+        .filter(|(src, dest)| fs::read(src).ok() != fs::read(dest).ok())
+        .collect();
+    let stale = placed
+        .under(root)
+        .filter(|file| file.is_file() && !wanted.contains(&key(file)))
+        .cloned()
+        .collect();
+
+    Ok(Changes { copy, stale })
+}
+
+/// Brings rhiza's Start Menu folder in line with `src`, only touching the files that changed
+/// so pinned tiles survive. Only files rhiza placed there are ever removed
+pub fn sync(config: &Config, scope: Scope) -> io::Result<()> {
+    let mut placed = Placed::load()?;
+    sync_dir(
+        config,
+        Path::new(&scope.src_dir()),
+        &config.start_menu_root(scope)?,
+        &mut placed,
+    )?;
+    placed.save()
+}
+
+fn sync_dir(config: &Config, src_dir: &Path, root: &Path, placed: &mut Placed) -> io::Result<()> {
+    fs::create_dir_all(root)?;
+
+    let changes = changes_dir(config, src_dir, root, placed)?;
+    for (src, dest) in &changes.copy {
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(src, dest)?;
+    }
+    // Links already in place from before rhiza recorded them are its own too
+    for (_, dest) in plan_dir(config, src_dir, root)? {
+        placed.record(&dest);
+    }
+    for file in &changes.stale {
+        fs::remove_file(file)?;
+        placed.forget(file);
+    }
+
+    // Category folders left empty
+    for entry in WalkDir::new(root)
+        .contents_first(true)
+        .into_iter()
+        .flatten()
+    {
+        if entry.file_type().is_dir() && entry.path() != root {
+            let _ = fs::remove_dir(entry.path());
+        }
+    }
+
+    if !changes.is_empty() {
+        say!(
+            "{}",
+            format!(
+                "Synced the Start Menu ({} updated, {} removed)",
+                changes.copy.len(),
+                changes.stale.len()
+            )
+            .purple()
+        );
+    }
+    Ok(())
+}
+
+/// A category as a relative folder, `Games/Emulators` is `Games\Emulators`
+pub fn category_dir(category: &str) -> PathBuf {
+    category
+        .split(['/', '\\'])
+        .map(clean)
+        .filter(|part| !part.is_empty() && part != "." && part != "..")
+        .collect()
+}
+
+/// `start_menu_folder` as a single folder name, refusing anything that would put rhiza's
+/// folder elsewhere than in the Programs folder, like `..`, `C:\` or an empty name
+pub fn folder_name(folder: &str) -> io::Result<String> {
+    let name = clean(folder);
+    if folder.contains(['/', '\\', ':']) || name.is_empty() || name == "." || name == ".." {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "start_menu_folder must be a single folder name, like rhiza, not '{}'",
+                folder
+            ),
+        ));
+    }
+    Ok(name)
+}
+
+// A folder name without the characters Windows doesn't allow in them
+fn clean(part: &str) -> String {
+    part.trim()
+        .chars()
+        .filter(|c| !matches!(c, '<' | '>' | ':' | '"' | '|' | '?' | '*'))
+        .collect()
+}
+
+fn key(path: &Path) -> String {
+    path.to_string_lossy().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::installer::Entry;
+
+    #[test]
+    fn categories_are_relative_folders() {
+        assert_eq!(
+            category_dir("Games/Emulators"),
+            Path::new("Games").join("Emulators")
+        );
+        assert_eq!(category_dir(" Dev\\Tools "), Path::new("Dev").join("Tools"));
+        assert_eq!(category_dir("../a:b/./c*"), Path::new("ab").join("c"));
+        assert_eq!(category_dir(""), PathBuf::new());
+    }
+
+    #[test]
+    fn folder_names_stay_in_the_programs_folder() {
+        assert_eq!(folder_name(" Apps ").unwrap(), "Apps");
+        assert_eq!(folder_name("my<apps>").unwrap(), "myapps");
+        for folder in [
+            "",
+            " ",
+            ".",
+            "..",
+            "a/b",
+            "a\\b",
+            "C:",
+            "C:\\Users",
+            "/",
+            "\\\\server",
+        ] {
+            let err = folder_name(folder).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{:?}", folder);
+        }
+
+        let config = Config {
+            start_menu_folder: Some("..".to_string()),
+            ..Config::default()
+        };
+        assert!(config.start_menu_root(Scope::User).is_err());
+    }
+
+    #[test]
+    fn sync_follows_src_and_only_removes_what_it_placed() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        let root = dir.path().join("Programs").join("rhiza");
+        fs::create_dir_all(&src).unwrap();
+        fs::create_dir_all(&root).unwrap();
+        fs::write(src.join("code.lnk"), "code").unwrap();
+        fs::write(src.join("game.lnk"), "game").unwrap();
+        fs::write(src.join("hidden.lnk"), "hidden").unwrap();
+        // Put there by hand, rhiza doesn't know it
+        fs::write(root.join("mine.lnk"), "mine").unwrap();
+
+        let mut config = Config::default();
+        config
+            .commands
+            .insert("code".to_string(), Entry::new("code".to_string()));
+        let mut game = Entry::new("game".to_string());
+        game.category = Some("Games/Emulators".to_string());
+        config.commands.insert("game".to_string(), game);
+        let mut hidden = Entry::new("hidden".to_string());
+        hidden.outputs = vec![Output::Shim];
+        config.commands.insert("hidden".to_string(), hidden);
+
+        let mut plan = plan_dir(&config, &src, &root).unwrap();
+        plan.sort();
+        let game_dest = root.join("Games").join("Emulators").join("game.lnk");
+        assert_eq!(
+            plan,
+            [
+                (src.join("code.lnk"), root.join("code.lnk")),
+                (src.join("game.lnk"), game_dest.clone()),
+            ]
+        );
+
+        let mut placed = Placed::default();
+        let changes = changes_dir(&config, &src, &root, &placed).unwrap();
+        assert_eq!(changes.copy.len(), 2);
+        assert!(changes.stale.is_empty());
+
+        sync_dir(&config, &src, &root, &mut placed).unwrap();
+        assert_eq!(fs::read_to_string(&game_dest).unwrap(), "game");
+        assert!(changes_dir(&config, &src, &root, &placed)
+            .unwrap()
+            .is_empty());
+
+        // Moving a command to another category removes the old copy and its empty folders
+        config.commands.get_mut("game").unwrap().category = None;
+        let changes = changes_dir(&config, &src, &root, &placed).unwrap();
+        assert_eq!(changes.stale, std::slice::from_ref(&game_dest));
+        sync_dir(&config, &src, &root, &mut placed).unwrap();
+        assert!(!game_dest.exists());
+        assert!(!root.join("Games").exists());
+        assert!(root.join("game.lnk").exists());
+
+        config.commands.remove("code");
+        config.commands.get_mut("game").unwrap().outputs = vec![Output::Shim];
+        sync_dir(&config, &src, &root, &mut placed).unwrap();
+        assert!(!root.join("game.lnk").exists());
+        // A command without an entry still gets its link, like before
+        assert!(root.join("code.lnk").exists());
+        assert!(root.join("mine.lnk").exists());
+    }
+}
@@ -0,0 +1,585 @@
+use colored::Colorize;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::installer::{self, Config, Scope};
+use crate::paths;
+use crate::report;
+use crate::worker;
+
+/// The working copy config.json is synced through, in `<rhiza home>\sync.json`
+#[derive(Serialize, Deserialize)]
+struct State {
+    work_tree: PathBuf,
+}
+
+/// Sets up syncing through `repo`: an existing working copy is used as is, anything else
+/// (a bare repository, a url) is cloned into `<rhiza home>\sync`.
+/// The config already there is merged with the local one, which wins on conflicts
+pub fn init(repo: &str) -> io::Result<()> {
+    if paths::sync_file().exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!(
+                "Sync is already set up, remove {} to start over",
+                paths::sync_file().display()
+            ),
+        ));
+    }
+
+    let work_tree = match source(repo)? {
+        Source::WorkTree(work_tree) => work_tree,
+        Source::Clone(repo) => {
+            let dir = paths::sync_dir();
+            git(
+                paths::home(),
+                &["clone", "--quiet", &repo, &dir.to_string_lossy()],
+            )?;
+            dir
+        }
+    };
+    say!("{} {}", "Syncing through".green(), work_tree.display());
+
+    let state = State { work_tree };
+    fs::write(paths::sync_file(), serde_json::to_string_pretty(&state)?)?;
+
+    let conflicts = merge_remote(&state.work_tree, true)?;
+    publish(&state.work_tree)?;
+    report::emit(&json!({ "work_tree": state.work_tree, "conflicts": conflicts }));
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+enum Source {
+    /// The top level of an existing working copy
+    WorkTree(PathBuf),
+    /// A url, or the absolute path of a local repository, to clone
+    Clone(String),
+}
+
+/// What `rhz sync init` was given. Local paths are made absolute since the clone runs from
+/// the rhiza home, and a folder inside a working copy is refused rather than taken for it
+fn source(repo: &str) -> io::Result<Source> {
+    let path = Path::new(repo);
+    if !path.exists() {
+        return Ok(Source::Clone(repo.to_string()));
+    }
+
+    let path = std::path::absolute(path)?;
+    if git(&path, &["rev-parse", "--is-bare-repository"])
+        .ok()
+        .as_deref()
+        != Some("false")
+    {
+        return Ok(Source::Clone(path.to_string_lossy().to_string()));
+    }
+    let top_level = PathBuf::from(git(&path, &["rev-parse", "--show-toplevel"])?);
+    if fs::canonicalize(&top_level)? != fs::canonicalize(&path)? {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} is inside the working copy {}, pass its top level or a repository of its own",
+                path.display(),
+                top_level.display()
+            ),
+        ));
+    }
+    Ok(Source::WorkTree(path))
+}
+
+/// Commits the local config and pushes it, merging in the remote one first
+pub fn push() -> io::Result<()> {
+    let state = state()?;
+    let conflicts = merge_remote(&state.work_tree, false)?;
+    publish(&state.work_tree)?;
+    report::emit(&json!({ "conflicts": conflicts }));
+    Ok(())
+}
+
+/// Merges the remote config into the local one key by key, then links the result
+pub fn pull() -> io::Result<()> {
+    let state = state()?;
+    let conflicts = merge_remote(&state.work_tree, false)?;
+    commit(&state.work_tree)?;
+    let run = worker::run(Scope::User)?;
+    report::emit(&json!({ "conflicts": conflicts, "run": run }));
+    Ok(())
+}
+
+fn state() -> io::Result<State> {
+    match fs::read_to_string(paths::sync_file()) {
+        Ok(content) => Ok(serde_json::from_str(&content)?),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "Sync isn't set up, see 'rhz sync init --help'",
+        )),
+        Err(err) => Err(err),
+    }
+}
+
+/// Fetches, merges the remote config.json into the local config and into the working copy,
+/// and returns the keys both sides changed. Those keep the local value
+fn merge_remote(work_tree: &Path, first: bool) -> io::Result<Vec<String>> {
+    let ours = normalized(&installer::read_config()?)?;
+    let mut conflicts = Vec::new();
+    let merged = merge_work_tree(work_tree, &ours, first, &mut conflicts)?;
+
+    for conflict in &conflicts {
+        let msg = format!("'{}' changed on both sides, kept the local value", conflict);
+        say!("{}", msg.yellow());
+    }
+
+    if merged != ours {
+        let config: Config = serde_json::from_value(merged)?;
+        config.write()?;
+        say!("{}", "Merged the remote config".green());
+    }
+    Ok(conflicts)
+}
+
+/// Merges `ours` with the config.json of the upstream branch and writes the result to the
+/// working copy. The common base is the last commit both sides share, none on the `first`
+/// sync of a machine. A working copy without upstream is merged with its own HEAD on the
+/// first sync, it may hold another machine's config
+fn merge_work_tree(
+    work_tree: &Path,
+    ours: &Value,
+    first: bool,
+    conflicts: &mut Vec<String>,
+) -> io::Result<Value> {
+    let upstream = if remote(work_tree)?.is_some() {
+        git(work_tree, &["fetch", "--quiet"])?;
+        git(work_tree, &["rev-parse", "--abbrev-ref", "@{upstream}"]).ok()
+    } else {
+        None
+    };
+
+    let empty = Value::Object(Map::new());
+    let merged = match &upstream {
+        // Nothing new on the remote, unless this machine never synced with it
+        Some(upstream) if first || !is_ancestor(work_tree, upstream, "HEAD") => {
+            let base = if first {
+                None
+            } else {
+                match git(work_tree, &["merge-base", "HEAD", upstream]) {
+                    Ok(base) => show(work_tree, &base)?,
+                    Err(_) => None,
+                }
+            };
+            let theirs = show(work_tree, upstream)?;
+            let merged = merge(
+                base.as_ref().unwrap_or(&empty),
+                ours,
+                theirs.as_ref().unwrap_or(&empty),
+                "",
+                conflicts,
+            );
+
+            if !is_ancestor(work_tree, upstream, "HEAD") {
+                if !has_commits(work_tree) || is_ancestor(work_tree, "HEAD", upstream) {
+                    git(work_tree, &["merge", "--quiet", "--ff-only", upstream])?;
+                } else {
+                    // Records the merge, the content is the key by key merge below
+                    git_as_user(
+                        work_tree,
+                        &["merge", "--quiet", "-s", "ours", "--no-commit", upstream],
+                    )?;
+                }
+            }
+            merged
+        }
+        None if first => match show(work_tree, "HEAD")? {
+            Some(committed) => merge(&empty, ours, &committed, "", conflicts),
+            None => ours.clone(),
+        },
+        _ => ours.clone(),
+    };
+
+    fs::write(work_tree.join("config.json"), pretty(&merged)?)?;
+    Ok(merged)
+}
+
+/// Commits config.json and pushes it if the working copy has a remote
+fn publish(work_tree: &Path) -> io::Result<()> {
+    commit(work_tree)?;
+    if let Some(remote) = remote(work_tree)? {
+        git(work_tree, &["push", "--quiet", "-u", &remote, "HEAD"])?;
+        say!("{} {}", "Pushed to".green(), remote);
+    }
+    Ok(())
+}
+
+fn commit(work_tree: &Path) -> io::Result<()> {
+    git(work_tree, &["add", "config.json"])?;
+    let merging = git(work_tree, &["rev-parse", "-q", "--verify", "MERGE_HEAD"]).is_ok();
+    let staged = git(work_tree, &["diff", "--cached", "--quiet"]).is_err();
+    if !merging && !staged {
+        say!("{}", "config.json is up to date".green());
+        return Ok(());
+    }
+
+    let host = env::var("COMPUTERNAME").unwrap_or_else(|_| "rhiza".to_string());
+    let message = if merging {
+        format!("Merge config.json on {}", host)
+    } else {
+        format!("Update config.json from {}", host)
+    };
+    git_as_user(work_tree, &["commit", "--quiet", "-m", &message])?;
+    say!("{} {}", "Committed".green(), message);
+    Ok(())
+}
+
+/// Three-way merge of json objects, key by key. Values changed on both sides are merged
+/// recursively if they're objects, and keep `ours` otherwise
+fn merge(
+    base: &Value,
+    ours: &Value,
+    theirs: &Value,
+    path: &str,
+    conflicts: &mut Vec<String>,
+) -> Value {
+    if ours == theirs || theirs == base {
+        return ours.clone();
+    }
+    if ours == base {
+        return theirs.clone();
+    }
+    // A key new on both sides, e.g on a fresh machine, where one side is still empty
+    if base.is_null() && is_empty(ours) {
+        return theirs.clone();
+    }
+    if base.is_null() && is_empty(theirs) {
+        return ours.clone();
+    }
+
+    let (Value::Object(ours), Value::Object(theirs)) = (ours, theirs) else {
+        conflicts.push(path.to_string());
+        return ours.clone();
+    };
+    let empty = Map::new();
+    let base = base.as_object().unwrap_or(&empty);
+
+    let mut merged = Map::new();
+    for key in ours.keys().chain(theirs.keys()) {
+        if merged.contains_key(key) {
+            continue;
+        }
+        let side = |map: &Map<String, Value>| map.get(key).cloned().unwrap_or(Value::Null);
+        let path = match path {
+            "" => key.to_string(),
+            path => format!("{}.{}", path, key),
+        };
+        let value = merge(&side(base), &side(ours), &side(theirs), &path, conflicts);
+        // Null stands for a key removed on one side, unless both sides still have it
+        let removed = !ours.contains_key(key) || !theirs.contains_key(key);
+        if !value.is_null() || !removed {
+            merged.insert(key.to_string(), value);
+        }
+    }
+    Value::Object(merged)
+}
+
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::Array(array) => array.is_empty(),
+        Value::Object(object) => object.is_empty(),
+        Value::String(string) => string.is_empty(),
+        _ => false,
+    }
+}
+
+/// The config as json with sorted keys, so it diffs the same on every machine
+fn normalized(config: &Config) -> io::Result<Value> {
+    Ok(serde_json::to_value(config)?)
+}
+
+fn pretty(value: &Value) -> io::Result<String> {
+    Ok(serde_json::to_string_pretty(value)? + "\n")
+}
+
+/// config.json at `rev`, None if it isn't there
+fn show(work_tree: &Path, rev: &str) -> io::Result<Option<Value>> {
+    match git(work_tree, &["show", &format!("{}:./config.json", rev)]) {
+        Ok(content) => Ok(Some(serde_json::from_str(&content)?)),
+        Err(_) => Ok(None),
+    }
+}
+
+fn remote(work_tree: &Path) -> io::Result<Option<String>> {
+    let remotes = git(work_tree, &["remote"])?;
+    Ok(remotes.lines().next().map(|remote| remote.to_string()))
+}
+
+fn has_commits(work_tree: &Path) -> bool {
+    git(work_tree, &["rev-parse", "-q", "--verify", "HEAD"]).is_ok()
+}
+
+fn is_ancestor(work_tree: &Path, ancestor: &str, rev: &str) -> bool {
+    git(work_tree, &["merge-base", "--is-ancestor", ancestor, rev]).is_ok()
+}
+
+/// Runs git for a command that records who did it, as `rhiza` on machines without a git
+/// identity
+fn git_as_user(work_tree: &Path, args: &[&str]) -> io::Result<String> {
+    if git(work_tree, &["config", "user.email"]).is_ok() {
+        return git(work_tree, args);
+    }
+    let host = env::var("COMPUTERNAME").unwrap_or_else(|_| "localhost".to_string());
+    let email = format!("user.email=rhiza@{}", host);
+    let mut with_identity = vec!["-c", "user.name=rhiza", "-c", &email];
+    with_identity.extend_from_slice(args);
+    git(work_tree, &with_identity)
+}
+
+/// Runs git in `dir`, returning its trimmed output or its error message
+fn git(dir: &Path, args: &[&str]) -> io::Result<String> {
+    let output = process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .map_err(|err| io::Error::new(err.kind(), format!("Couldn't run git: {}", err)))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(io::Error::other(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            stderr
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_sources_are_absolute_top_levels() {
+        let dir = tempfile::tempdir().unwrap();
+        let bare = dir.path().join("config.git");
+        git(
+            dir.path(),
+            &["init", "--quiet", "--bare", &bare.to_string_lossy()],
+        )
+        .unwrap();
+        let work_tree = dir.path().join("work");
+        fs::create_dir_all(work_tree.join("sub")).unwrap();
+        git(&work_tree, &["init", "--quiet"]).unwrap();
+
+        // Relative paths are resolved against the current directory, not the rhiza home
+        let cwd = env::current_dir().unwrap();
+        let relative = pathdiff(&bare, &cwd);
+        let Source::Clone(cloned) = source(&relative.to_string_lossy()).unwrap() else {
+            panic!("a bare repository is cloned");
+        };
+        assert!(Path::new(&cloned).is_absolute());
+        assert_eq!(
+            fs::canonicalize(cloned).unwrap(),
+            fs::canonicalize(&bare).unwrap()
+        );
+        assert_eq!(
+            source(&work_tree.to_string_lossy()).unwrap(),
+            Source::WorkTree(work_tree.clone())
+        );
+        let err = source(&work_tree.join("sub").to_string_lossy()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            source("https://example.com/config.git").unwrap(),
+            Source::Clone("https://example.com/config.git".to_string())
+        );
+    }
+
+    // `path` relative to `base`, through `..`s
+    fn pathdiff(path: &Path, base: &Path) -> PathBuf {
+        let mut relative = PathBuf::new();
+        let mut base = base;
+        while !path.starts_with(base) {
+            relative.push("..");
+            base = base.parent().unwrap();
+        }
+        relative.join(path.strip_prefix(base).unwrap())
+    }
+
+    fn merged(base: Value, ours: Value, theirs: Value) -> (Value, Vec<String>) {
+        let mut conflicts = Vec::new();
+        let merged = merge(&base, &ours, &theirs, "", &mut conflicts);
+        (merged, conflicts)
+    }
+
+    #[test]
+    fn removed_on_one_side_and_changed_on_the_other_keeps_ours() {
+        let base = json!({ "commands": { "code": { "target": "C:\\code.exe" } } });
+        let changed = json!({ "commands": { "code": { "target": "D:\\code.exe" } } });
+        let removed = json!({ "commands": {} });
+
+        let (value, conflicts) = merged(base.clone(), removed.clone(), changed.clone());
+        assert_eq!(value, removed);
+        assert_eq!(conflicts, ["commands.code"]);
+
+        let (value, conflicts) = merged(base, changed.clone(), removed);
+        assert_eq!(value, changed);
+        assert_eq!(conflicts, ["commands.code"]);
+    }
+
+    #[test]
+    fn removals_on_one_side_apply() {
+        let base = json!({ "commands": { "code": "C:\\code.exe", "steam": "C:\\steam.exe" } });
+        let ours = json!({ "commands": { "code": "C:\\code.exe" } });
+        let (value, conflicts) = merged(base.clone(), ours.clone(), base);
+        assert_eq!(value, ours);
+        assert!(conflicts.is_empty());
+    }
+
+    #[test]
+    fn nested_entries_merge_field_by_field() {
+        let base =
+            json!({ "commands": { "code": { "target": "C:\\code.exe", "category": null } } });
+        let ours =
+            json!({ "commands": { "code": { "target": "C:\\code.exe", "category": "dev" } } });
+        let theirs =
+            json!({ "commands": { "code": { "target": "D:\\code.exe", "category": null } } });
+
+        let (value, conflicts) = merged(base.clone(), ours, theirs);
+        assert_eq!(
+            value,
+            json!({ "commands": { "code": { "target": "D:\\code.exe", "category": "dev" } } })
+        );
+        assert!(conflicts.is_empty());
+
+        let ours =
+            json!({ "commands": { "code": { "target": "E:\\code.exe", "category": null } } });
+        let theirs =
+            json!({ "commands": { "code": { "target": "D:\\code.exe", "category": null } } });
+        let (value, conflicts) = merged(base, ours.clone(), theirs);
+        assert_eq!(value, ours);
+        assert_eq!(conflicts, ["commands.code.target"]);
+    }
+
+    #[test]
+    fn first_sync_unions_both_sides() {
+        let ours = json!({ "commands": { "code": "C:\\code.exe" }, "active": [], "hooks": {} });
+        let theirs = json!({
+            "commands": { "steam": "C:\\steam.exe" },
+            "active": ["work"],
+            "hooks": { "post-run": "echo done" },
+        });
+
+        let (value, conflicts) = merged(json!({}), ours, theirs);
+        assert_eq!(
+            value,
+            json!({
+                "commands": { "code": "C:\\code.exe", "steam": "C:\\steam.exe" },
+                "active": ["work"],
+                "hooks": { "post-run": "echo done" },
+            })
+        );
+        assert!(conflicts.is_empty());
+    }
+
+    fn clone(bare: &Path, dir: &Path, name: &str) -> PathBuf {
+        let path = dir.join(name);
+        git(
+            dir,
+            &[
+                "clone",
+                "--quiet",
+                &bare.to_string_lossy(),
+                &path.to_string_lossy(),
+            ],
+        )
+        .unwrap();
+        path
+    }
+
+    fn committed(work_tree: &Path) -> Value {
+        show(work_tree, "HEAD").unwrap().unwrap()
+    }
+
+    #[test]
+    fn syncs_machines_through_a_bare_repo() {
+        let dir = tempfile::tempdir().unwrap();
+        let bare = dir.path().join("remote.git");
+        git(
+            dir.path(),
+            &["init", "--quiet", "--bare", &bare.to_string_lossy()],
+        )
+        .unwrap();
+        let mut conflicts = Vec::new();
+
+        // The first machine starts the repository
+        let a = clone(&bare, dir.path(), "a");
+        let code = json!({ "commands": { "code": "C:\\code.exe" } });
+        assert_eq!(
+            merge_work_tree(&a, &code, true, &mut conflicts).unwrap(),
+            code
+        );
+        publish(&a).unwrap();
+
+        // The second one keeps its commands and gets the first one's
+        let b = clone(&bare, dir.path(), "b");
+        let steam = json!({ "commands": { "steam": "C:\\steam.exe" } });
+        let both = json!({ "commands": { "code": "C:\\code.exe", "steam": "C:\\steam.exe" } });
+        assert_eq!(
+            merge_work_tree(&b, &steam, true, &mut conflicts).unwrap(),
+            both
+        );
+        publish(&b).unwrap();
+        assert_eq!(committed(&b), both);
+
+        // Meanwhile the first one swapped code for notes
+        let notes = json!({ "commands": { "notes": "C:\\notes.exe" } });
+        let merged = merge_work_tree(&a, &notes, false, &mut conflicts).unwrap();
+        let expected =
+            json!({ "commands": { "notes": "C:\\notes.exe", "steam": "C:\\steam.exe" } });
+        assert_eq!(merged, expected);
+        publish(&a).unwrap();
+        assert_eq!(committed(&a), expected);
+
+        // Which the second one fast-forwards to
+        assert_eq!(
+            merge_work_tree(&b, &both, false, &mut conflicts).unwrap(),
+            expected
+        );
+        let config = fs::read_to_string(b.join("config.json")).unwrap();
+        assert_eq!(config, pretty(&expected).unwrap());
+        assert_eq!(
+            git(&b, &["rev-parse", "HEAD"]).unwrap(),
+            git(&a, &["rev-parse", "HEAD"]).unwrap()
+        );
+
+        // Nothing new on the remote, the local config stays as it is
+        let local = json!({ "commands": { "steam": "C:\\steam.exe" } });
+        assert_eq!(
+            merge_work_tree(&b, &local, false, &mut conflicts).unwrap(),
+            local
+        );
+        assert!(conflicts.is_empty());
+    }
+
+    #[test]
+    fn first_sync_of_a_working_copy_keeps_its_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let work_tree = dir.path().join("configs");
+        fs::create_dir(&work_tree).unwrap();
+        git(&work_tree, &["init", "--quiet"]).unwrap();
+        let code = json!({ "commands": { "code": "C:\\code.exe" } });
+        fs::write(work_tree.join("config.json"), pretty(&code).unwrap()).unwrap();
+        commit(&work_tree).unwrap();
+
+        let mut conflicts = Vec::new();
+        let steam = json!({ "commands": { "steam": "C:\\steam.exe" } });
+        let merged = merge_work_tree(&work_tree, &steam, true, &mut conflicts).unwrap();
+        let both = json!({ "commands": { "code": "C:\\code.exe", "steam": "C:\\steam.exe" } });
+        assert_eq!(merged, both);
+        publish(&work_tree).unwrap();
+        assert_eq!(committed(&work_tree), both);
+        assert!(conflicts.is_empty());
+    }
+}
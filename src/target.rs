@@ -0,0 +1,280 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// Every kind of target rhiza knows how to launch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TargetKind {
+    Exe,
+    Lnk,
+    Url,
+    AppRef,
+    Batch,
+    PowerShell,
+    Python,
+    Jar,
+    Folder,
+}
+
+/// How a target ends up in `~/.rhiza/src`.
+pub enum Launcher {
+    /// The file itself is already launchable, it is copied with the given extension
+    Copy(&'static str),
+    /// A shortcut to `program` is created, passing `args` when set
+    Shortcut {
+        program: PathBuf,
+        args: Option<String>,
+    },
+}
+
+impl TargetKind {
+    /// Extensions the `add` search offers
+    pub const EXTENSIONS: [&'static str; 10] = [
+        "exe",
+        "lnk",
+        "url",
+        "appref-ms",
+        "bat",
+        "cmd",
+        "ps1",
+        "py",
+        "pyw",
+        "jar",
+    ];
+
+    pub fn detect(path: &Path) -> Option<TargetKind> {
+        if path.is_dir() {
+            return Some(TargetKind::Folder);
+        }
+
+        let ext = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase());
+        let by_ext = match ext.as_deref() {
+            Some("exe") => Some(TargetKind::Exe),
+            Some("lnk") => Some(TargetKind::Lnk),
+            Some("url") => Some(TargetKind::Url),
+            Some("appref-ms") => Some(TargetKind::AppRef),
+            Some("bat") | Some("cmd") => Some(TargetKind::Batch),
+            Some("ps1") => Some(TargetKind::PowerShell),
+            Some("py") | Some("pyw") => Some(TargetKind::Python),
+            Some("jar") => Some(TargetKind::Jar),
+            _ => None,
+        };
+
+        by_ext.or_else(|| sniff(path).ok().flatten())
+    }
+
+    pub fn launcher(&self, path: &Path) -> io::Result<Launcher> {
+        let quoted = format!("\"{}\"", path.display());
+        let launcher = match self {
+            TargetKind::Lnk => Launcher::Copy("lnk"),
+            TargetKind::Url => Launcher::Copy("url"),
+            TargetKind::AppRef => Launcher::Copy("appref-ms"),
+            TargetKind::Exe | TargetKind::Batch => Launcher::Shortcut {
+                program: path.to_path_buf(),
+                args: None,
+            },
+            TargetKind::PowerShell => Launcher::Shortcut {
+                program: interpreter(&["pwsh", "powershell"])?,
+                args: Some(format!(
+                    "-NoProfile -ExecutionPolicy Bypass -File {}",
+                    quoted
+                )),
+            },
+            TargetKind::Python => {
+                let is_gui = path
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("pyw"));
+                let names: &[&str] = if is_gui {
+                    &["pythonw", "pyw"]
+                } else {
+                    &["python", "py"]
+                };
+                Launcher::Shortcut {
+                    program: interpreter(names)?,
+                    args: Some(quoted),
+                }
+            }
+            TargetKind::Jar => Launcher::Shortcut {
+                program: interpreter(&["java"])?,
+                args: Some(format!("-jar {}", quoted)),
+            },
+            TargetKind::Folder => Launcher::Shortcut {
+                program: interpreter(&["explorer"])?,
+                args: Some(quoted),
+            },
+        };
+
+        Ok(launcher)
+    }
+}
+
+fn interpreter(names: &[&str]) -> io::Result<PathBuf> {
+    names
+        .iter()
+        .find_map(|name| which::which(name).ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Couldn't find {} in PATH", names.join(" or ")),
+            )
+        })
+}
+
+// Guess the kind of a file without a known extension from its first bytes
+fn sniff(path: &Path) -> io::Result<Option<TargetKind>> {
+    let mut head = Vec::new();
+    fs::File::open(path)?.take(512).read_to_end(&mut head)?;
+
+    if head.starts_with(b"MZ") {
+        return Ok(Some(TargetKind::Exe));
+    }
+    if head.starts_with(&[0x4C, 0x00, 0x00, 0x00, 0x01, 0x14, 0x02, 0x00]) {
+        return Ok(Some(TargetKind::Lnk));
+    }
+    if head.starts_with(b"PK\x03\x04") {
+        return Ok(Some(TargetKind::Jar));
+    }
+
+    // Text based kinds, .appref-ms files are usually utf-16
+    let text = if head.starts_with(&[0xFF, 0xFE]) {
+        let units: Vec<u16> = head[2..]
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        String::from_utf8_lossy(&head).to_string()
+    };
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+    let first_line = text.lines().next().unwrap_or("").to_lowercase();
+
+    let kind = if first_line.starts_with("[internetshortcut]") {
+        Some(TargetKind::Url)
+    } else if first_line.starts_with("#!") && first_line.contains("python") {
+        Some(TargetKind::Python)
+    } else if first_line.contains(".application#") && first_line.contains("publickeytoken=") {
+        Some(TargetKind::AppRef)
+    } else {
+        None
+    };
+
+    Ok(kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_kinds_by_extension() {
+        let kinds = [
+            ("app.exe", TargetKind::Exe),
+            ("App.LNK", TargetKind::Lnk),
+            ("site.url", TargetKind::Url),
+            ("app.appref-ms", TargetKind::AppRef),
+            ("build.bat", TargetKind::Batch),
+            ("build.cmd", TargetKind::Batch),
+            ("script.ps1", TargetKind::PowerShell),
+            ("script.py", TargetKind::Python),
+            ("gui.pyw", TargetKind::Python),
+            ("tool.jar", TargetKind::Jar),
+        ];
+        for (file, kind) in kinds {
+            assert_eq!(TargetKind::detect(Path::new(file)), Some(kind), "{}", file);
+        }
+        // Every extension the search offers is known
+        for ext in TargetKind::EXTENSIONS {
+            let file = format!("file.{}", ext);
+            assert!(TargetKind::detect(Path::new(&file)).is_some(), "{}", ext);
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(TargetKind::detect(dir.path()), Some(TargetKind::Folder));
+        assert_eq!(TargetKind::detect(&dir.path().join("missing")), None);
+    }
+
+    #[test]
+    fn sniffs_files_without_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        let appref: Vec<u8> = [0xFF, 0xFE]
+            .into_iter()
+            .chain(
+                "https://example.com/app.application#app.application, Culture=neutral, PublicKeyToken=abc"
+                    .encode_utf16()
+                    .flat_map(|unit| unit.to_le_bytes()),
+            )
+            .collect();
+        let files: [(&str, Vec<u8>, Option<TargetKind>); 8] = [
+            ("exe", b"MZ\x90\x00".to_vec(), Some(TargetKind::Exe)),
+            (
+                "lnk",
+                vec![0x4C, 0x00, 0x00, 0x00, 0x01, 0x14, 0x02, 0x00, 0x00],
+                Some(TargetKind::Lnk),
+            ),
+            ("jar", b"PK\x03\x04".to_vec(), Some(TargetKind::Jar)),
+            (
+                "url",
+                b"\xEF\xBB\xBF[InternetShortcut]\r\nURL=https://example.com".to_vec(),
+                Some(TargetKind::Url),
+            ),
+            (
+                "python",
+                b"#!/usr/bin/env python3\nprint()".to_vec(),
+                Some(TargetKind::Python),
+            ),
+            ("appref", appref, Some(TargetKind::AppRef)),
+            ("shell", b"#!/bin/sh\necho".to_vec(), None),
+            ("empty", Vec::new(), None),
+        ];
+        for (name, content, kind) in files {
+            let path = dir.path().join(name);
+            fs::write(&path, content).unwrap();
+            assert_eq!(TargetKind::detect(&path), kind, "{}", name);
+        }
+    }
+
+    #[test]
+    fn picks_a_launcher_per_kind() {
+        let path = Path::new("C:\\tools\\app");
+        for (kind, ext) in [
+            (TargetKind::Lnk, "lnk"),
+            (TargetKind::Url, "url"),
+            (TargetKind::AppRef, "appref-ms"),
+        ] {
+            match kind.launcher(path).unwrap() {
+                Launcher::Copy(copied) => assert_eq!(copied, ext),
+                Launcher::Shortcut { .. } => panic!("{:?} is copied", kind),
+            }
+        }
+        for kind in [TargetKind::Exe, TargetKind::Batch] {
+            match kind.launcher(path).unwrap() {
+                Launcher::Shortcut { program, args } => {
+                    assert_eq!(program, path);
+                    assert_eq!(args, None);
+                }
+                Launcher::Copy(_) => panic!("{:?} gets a shortcut", kind),
+            }
+        }
+
+        // Scripts go through their interpreter when it's installed
+        let quoted = format!("\"{}\"", path.display());
+        let expected = [
+            (
+                TargetKind::PowerShell,
+                format!("-NoProfile -ExecutionPolicy Bypass -File {}", quoted),
+            ),
+            (TargetKind::Python, quoted.clone()),
+            (TargetKind::Jar, format!("-jar {}", quoted)),
+            (TargetKind::Folder, quoted.clone()),
+        ];
+        for (kind, expected) in expected {
+            match kind.launcher(path) {
+                Ok(Launcher::Shortcut { args, .. }) => assert_eq!(args, Some(expected)),
+                Ok(Launcher::Copy(_)) => panic!("{:?} gets a shortcut", kind),
+                Err(err) => assert_eq!(err.kind(), io::ErrorKind::NotFound),
+            }
+        }
+    }
+}
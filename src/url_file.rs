@@ -0,0 +1,397 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const SECTION: &str = "InternetShortcut";
+
+/// Text encoding an Internet Shortcut was stored in, kept so it can be written back as-is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    /// Anything that isn't valid UTF-8, read as Latin-1 so every byte maps to a char
+    Ansi,
+}
+
+#[derive(Debug, Clone)]
+enum Kind {
+    Section(String),
+    Pair { key: String, value: String },
+    Other,
+}
+
+#[derive(Debug, Clone)]
+struct Line {
+    kind: Kind,
+    /// The bytes the line was read from, without its line ending. None once it changed
+    raw: Option<Vec<u8>>,
+    /// The encoded line ending it was read with, empty for new lines and the last line
+    ending: Vec<u8>,
+}
+
+/// An INI style `.url` file.
+///
+/// Every line is kept as the bytes it was read from (comments, unknown sections and keys
+/// included, with their line endings) so that only the lines that change are rewritten.
+#[derive(Debug, Clone)]
+pub struct UrlFile {
+    lines: Vec<Line>,
+    encoding: Encoding,
+    /// The line ending of new lines: the first one of the file, `\r\n` by default
+    newline: &'static str,
+    /// Whether the last line ends with a line ending
+    final_newline: bool,
+}
+
+impl UrlFile {
+    pub fn new(url: &str) -> Self {
+        let mut file = UrlFile {
+            lines: vec![Line::new(Kind::Section(SECTION.to_string()))],
+            encoding: Encoding::Utf8,
+            newline: "\r\n",
+            final_newline: true,
+        };
+        file.set_url(url);
+        file
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        Ok(Self::parse(&bytes))
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    pub fn parse(bytes: &[u8]) -> Self {
+        let (encoding, body) = detect(bytes);
+        let (lf, cr) = (encode("\n", encoding), encode("\r", encoding));
+        let unit = lf.len();
+
+        // Split on the encoded line feeds, keeping each line ending as it is
+        let mut lines = Vec::new();
+        let mut newline = None;
+        let mut start = 0;
+        let mut at = 0;
+        while at + unit <= body.len() {
+            if body[at..at + unit] != lf {
+                at += unit;
+                continue;
+            }
+            let end = if at >= start + unit && body[at - unit..at] == cr {
+                at - unit
+            } else {
+                at
+            };
+            newline.get_or_insert(if end < at { "\r\n" } else { "\n" });
+            lines.push(Line::read(
+                &body[start..end],
+                &body[end..at + unit],
+                encoding,
+            ));
+            at += unit;
+            start = at;
+        }
+        // A file ending with a line ending has no line after it. An odd byte out of a
+        // UTF-16 file stays in the last line
+        let final_newline = start == body.len();
+        if !final_newline {
+            lines.push(Line::read(&body[start..], &[], encoding));
+        }
+
+        UrlFile {
+            lines,
+            encoding,
+            newline: newline.unwrap_or("\r\n"),
+            final_newline,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = bom(self.encoding).to_vec();
+        let newline = encode(self.newline, self.encoding);
+        for (i, line) in self.lines.iter().enumerate() {
+            match &line.raw {
+                Some(raw) => bytes.extend_from_slice(raw),
+                None => bytes.extend(encode(&line.render(), self.encoding)),
+            }
+            let last = i + 1 == self.lines.len();
+            if !line.ending.is_empty() {
+                bytes.extend_from_slice(&line.ending);
+            } else if !last || self.final_newline {
+                bytes.extend_from_slice(&newline);
+            }
+        }
+        bytes
+    }
+
+    /// Looks up a key, matching section and key names case-insensitively.
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        let mut in_section = false;
+        for line in &self.lines {
+            match &line.kind {
+                Kind::Section(name) => in_section = name.eq_ignore_ascii_case(section),
+                Kind::Pair { key: k, value } if in_section && k.eq_ignore_ascii_case(key) => {
+                    return Some(value);
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Sets a key, replacing it in place or appending it to the end of its section.
+    pub fn set(&mut self, section: &str, key: &str, value: &str) {
+        let mut in_section = false;
+        let mut insert_at = None;
+        for (i, line) in self.lines.iter_mut().enumerate() {
+            match &mut line.kind {
+                Kind::Section(name) => {
+                    in_section = name.eq_ignore_ascii_case(section);
+                    if in_section {
+                        insert_at = Some(i + 1);
+                    }
+                }
+                Kind::Pair { key: k, value: v } if in_section => {
+                    if k.eq_ignore_ascii_case(key) {
+                        if v != value {
+                            *v = value.to_string();
+                            line.raw = None;
+                        }
+                        return;
+                    }
+                    insert_at = Some(i + 1);
+                }
+                _ => {}
+            }
+        }
+
+        let pair = Line::new(Kind::Pair {
+            key: key.to_string(),
+            value: value.to_string(),
+        });
+        match insert_at {
+            Some(i) => self.lines.insert(i, pair),
+            None => {
+                self.lines
+                    .push(Line::new(Kind::Section(section.to_string())));
+                self.lines.push(pair);
+            }
+        }
+    }
+
+    pub fn url(&self) -> Option<&str> {
+        self.get(SECTION, "URL")
+    }
+
+    pub fn set_url(&mut self, url: &str) {
+        self.set(SECTION, "URL", url);
+    }
+
+    pub fn icon_file(&self) -> Option<&str> {
+        self.get(SECTION, "IconFile")
+    }
+
+    pub fn icon_index(&self) -> i32 {
+        self.get(SECTION, "IconIndex")
+            .and_then(|index| index.parse().ok())
+            .unwrap_or(0)
+    }
+
+    pub fn set_icon(&mut self, file: &str, index: i32) {
+        self.set(SECTION, "IconFile", file);
+        self.set(SECTION, "IconIndex", &index.to_string());
+    }
+}
+
+impl Line {
+    fn new(kind: Kind) -> Self {
+        Line {
+            kind,
+            raw: None,
+            ending: Vec::new(),
+        }
+    }
+
+    fn read(raw: &[u8], ending: &[u8], encoding: Encoding) -> Self {
+        let text = decode(raw, encoding);
+        let trimmed = text.trim();
+        let kind = if trimmed.starts_with('[') && trimmed.ends_with(']') {
+            Kind::Section(trimmed[1..trimmed.len() - 1].trim().to_string())
+        } else if trimmed.starts_with(';') || trimmed.starts_with('#') {
+            Kind::Other
+        } else if let Some((key, value)) = text.split_once('=') {
+            Kind::Pair {
+                key: key.trim().to_string(),
+                value: value.trim().to_string(),
+            }
+        } else {
+            Kind::Other
+        };
+        Line {
+            kind,
+            raw: Some(raw.to_vec()),
+            ending: ending.to_vec(),
+        }
+    }
+
+    /// How a new or changed line is written
+    fn render(&self) -> String {
+        match &self.kind {
+            Kind::Section(name) => format!("[{}]", name),
+            Kind::Pair { key, value } => format!("{}={}", key, value),
+            Kind::Other => String::new(),
+        }
+    }
+}
+
+/// The encoding of a file and its bytes after the byte order mark
+fn detect(bytes: &[u8]) -> (Encoding, &[u8]) {
+    match bytes {
+        [0xEF, 0xBB, 0xBF, rest @ ..] => (Encoding::Utf8Bom, rest),
+        [0xFF, 0xFE, rest @ ..] => (Encoding::Utf16Le, rest),
+        [0xFE, 0xFF, rest @ ..] => (Encoding::Utf16Be, rest),
+        _ if std::str::from_utf8(bytes).is_ok() => (Encoding::Utf8, bytes),
+        _ => (Encoding::Ansi, bytes),
+    }
+}
+
+fn bom(encoding: Encoding) -> &'static [u8] {
+    match encoding {
+        Encoding::Utf8Bom => &[0xEF, 0xBB, 0xBF],
+        Encoding::Utf16Le => &[0xFF, 0xFE],
+        Encoding::Utf16Be => &[0xFE, 0xFF],
+        Encoding::Utf8 | Encoding::Ansi => &[],
+    }
+}
+
+fn decode(bytes: &[u8], encoding: Encoding) -> String {
+    let utf16 = |from_bytes: fn([u8; 2]) -> u16| {
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|pair| from_bytes([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    };
+    match encoding {
+        Encoding::Utf8 | Encoding::Utf8Bom => String::from_utf8_lossy(bytes).to_string(),
+        Encoding::Utf16Le => utf16(u16::from_le_bytes),
+        Encoding::Utf16Be => utf16(u16::from_be_bytes),
+        Encoding::Ansi => bytes.iter().map(|&b| char::from(b)).collect(),
+    }
+}
+
+fn encode(content: &str, encoding: Encoding) -> Vec<u8> {
+    match encoding {
+        Encoding::Utf8 | Encoding::Utf8Bom => content.as_bytes().to_vec(),
+        Encoding::Utf16Le => content.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+        Encoding::Utf16Be => content.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+        Encoding::Ansi => content
+            .chars()
+            .map(|c| u8::try_from(c).unwrap_or(b'?'))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16(content: &str, big_endian: bool) -> Vec<u8> {
+        let (bom, encoding) = if big_endian {
+            ([0xFE, 0xFF], Encoding::Utf16Be)
+        } else {
+            ([0xFF, 0xFE], Encoding::Utf16Le)
+        };
+        [&bom[..], &encode(content, encoding)].concat()
+    }
+
+    #[test]
+    fn untouched_files_round_trip() {
+        let samples: Vec<Vec<u8>> = vec![
+            b"[InternetShortcut]\r\nURL=https://example.com\r\n".to_vec(),
+            b"[InternetShortcut]\nURL=https://example.com".to_vec(),
+            b"; comment\r\n\r\n[{000214A0-0000-0000-C000-000000000046}]\r\nProp3=19,11\n[InternetShortcut]\r\n  URL = https://example.com  \r\nIDList=\r\nweird line\r\n\r\n".to_vec(),
+            b"\xEF\xBB\xBF[InternetShortcut]\r\nURL=https://example.com/\xC3\xA9\r\n".to_vec(),
+            b"[InternetShortcut]\r\nURL=https://example.com/caf\xE9\r\n".to_vec(),
+            b"[InternetShortcut]\rURL=https://example.com\r".to_vec(),
+            utf16("[InternetShortcut]\r\nURL=https://example.com/é\r\n", false),
+            utf16("[InternetShortcut]\nURL=https://example.com", true),
+            [utf16("[InternetShortcut]\r\n", false), vec![0x55]].concat(),
+            Vec::new(),
+        ];
+        for bytes in samples {
+            assert_eq!(UrlFile::parse(&bytes).to_bytes(), bytes, "{:?}", bytes);
+        }
+    }
+
+    #[test]
+    fn reads_values_in_every_encoding() {
+        let utf8 = UrlFile::parse(b"[InternetShortcut]\r\nURL=https://example.com/\xC3\xA9\r\n");
+        assert_eq!(utf8.url(), Some("https://example.com/é"));
+        let ansi = UrlFile::parse(b"[internetshortcut]\nurl = https://example.com/caf\xE9\n");
+        assert_eq!(ansi.url(), Some("https://example.com/café"));
+        let utf16 = UrlFile::parse(&utf16("[InternetShortcut]\r\nURL=https://a.b/é", true));
+        assert_eq!(utf16.url(), Some("https://a.b/é"));
+    }
+
+    #[test]
+    fn only_changed_lines_are_rewritten() {
+        let bytes = b"; keep me\n[InternetShortcut]\n  URL = https://example.com  \nIconIndex=3\n[Other]\nX=1\n";
+        let mut file = UrlFile::parse(bytes);
+        file.set_icon("C:\\icons\\app.ico", 0);
+        assert_eq!(
+            String::from_utf8(file.to_bytes()).unwrap(),
+            "; keep me\n[InternetShortcut]\n  URL = https://example.com  \nIconIndex=0\nIconFile=C:\\icons\\app.ico\n[Other]\nX=1\n"
+        );
+
+        // Setting a key to the value it has leaves its line alone
+        let mut file = UrlFile::parse(bytes);
+        file.set_url("https://example.com");
+        assert_eq!(file.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn new_lines_follow_the_file() {
+        let mut file = UrlFile::parse(b"[InternetShortcut]\nURL=https://example.com");
+        file.set_icon("C:\\app.ico", 1);
+        assert_eq!(
+            file.to_bytes(),
+            b"[InternetShortcut]\nURL=https://example.com\nIconFile=C:\\app.ico\nIconIndex=1"
+        );
+
+        let mut file = UrlFile::parse(&utf16("[InternetShortcut]\r\nURL=https://a.b\r\n", false));
+        file.set_icon("C:\\é.ico", 0);
+        assert_eq!(
+            file.to_bytes(),
+            utf16(
+                "[InternetShortcut]\r\nURL=https://a.b\r\nIconFile=C:\\é.ico\r\nIconIndex=0\r\n",
+                false
+            )
+        );
+
+        let mut file = UrlFile::parse(b"; only a comment\n");
+        file.set_url("https://a.b");
+        assert_eq!(
+            file.to_bytes(),
+            b"; only a comment\n[InternetShortcut]\nURL=https://a.b\n"
+        );
+    }
+
+    #[test]
+    fn new_files() {
+        let mut file = UrlFile::new("steam://run/570");
+        file.set_icon("C:\\dota.ico", 0);
+        assert_eq!(
+            file.to_bytes(),
+            b"[InternetShortcut]\r\nURL=steam://run/570\r\nIconFile=C:\\dota.ico\r\nIconIndex=0\r\n"
+        );
+        assert_eq!(
+            UrlFile::parse(&file.to_bytes()).icon_file(),
+            Some("C:\\dota.ico")
+        );
+    }
+}
@@ -1,22 +1,56 @@
 use colored::Colorize;
 use inquire::{Confirm, InquireError, MultiSelect, Text};
-use shellexpand::tilde;
+use serde_derive::Serialize;
 use std::{
-    fs,
-    io::{self, Write},
-    path::Path,
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
 };
 use walkdir::{DirEntry, WalkDir};
 
-use crate::installer;
+use crate::hooks::{self, Hook};
+use crate::icon;
+use crate::installer::{self, Config, Entry, Output, Scope};
+use crate::lnk::{self, ShellLink};
+use crate::outputs;
+use crate::path_env::{self, RegistryEnv};
+use crate::shell;
+use crate::shim::{self, Shim};
+use crate::start_menu;
+use crate::target::{Launcher, TargetKind};
+use crate::url_file::UrlFile;
+
+/// What `crawl` found and what was done with each candidate
+#[derive(Default, Serialize)]
+pub struct CrawlReport {
+    pub candidates: Vec<Candidate>,
+}
+
+#[derive(Serialize)]
+pub struct Candidate {
+    pub path: String,
+    pub decision: Decision,
+    /// The command it was added as
+    pub name: Option<String>,
+}
 
-pub fn crawl_directory(dirs: Vec<&str>) -> Result<Vec<String>, InquireError> {
-    let executables = Vec::new();
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Decision {
+    Added,
+    /// Not selected, and hidden from future crawls
+    Hidden,
+    /// Not selected, or not overriding an existing command
+    Ignored,
+}
+
+pub fn crawl_directory(dirs: Vec<&str>) -> Result<CrawlReport, InquireError> {
+    let mut report = CrawlReport::default();
     let mut config = installer::check()?;
     let mut abs_skips = config.expand();
     let mut candidates = Vec::new();
 
-    let target_extensions = ["exe", "lnk", "url"];
+    let target_extensions = ["exe", "lnk", "url", "appref-ms"];
     let skips = vec![
         "Windows Kits",
         "Windows Accessories",
@@ -53,7 +87,7 @@ pub fn crawl_directory(dirs: Vec<&str>) -> Result<Vec<String>, InquireError> {
             if is_executable(&entry, &target_extensions) && is_user_friendly(&entry) {
                 if let Some(path) = entry.path().to_str() {
                     let path = path.to_string();
-                    let path_exists = config.commands.values().any(|cmd| cmd == &path);
+                    let path_exists = config.current().values().any(|cmd| cmd.target == path);
                     let skipped_before = config.skipped.contains(&path.to_owned());
                     let expanded_exists = file_exists(&abs_skips, &path)?;
                     if path_exists || skipped_before || expanded_exists {
@@ -66,8 +100,16 @@ pub fn crawl_directory(dirs: Vec<&str>) -> Result<Vec<String>, InquireError> {
     }
 
     if candidates.is_empty() {
-        return Ok(vec![]);
+        return Ok(report);
     }
+    report.candidates = candidates
+        .iter()
+        .map(|path| Candidate {
+            path: path.to_string(),
+            decision: Decision::Ignored,
+            name: None,
+        })
+        .collect();
 
     let selected = MultiSelect::new("Select apps to add:\n", candidates.clone())
         .without_filtering()
@@ -77,12 +119,12 @@ pub fn crawl_directory(dirs: Vec<&str>) -> Result<Vec<String>, InquireError> {
     // adds selected
     for path in selected.clone() {
         let entry = Path::new(&path);
-        let place_holder = get_name(&entry)?;
+        let place_holder = get_name(entry)?;
 
         let prompt = format!("for {}\nhow to call it?", path.purple());
         let name = Text::new(&prompt).with_default(&place_holder).prompt()?;
 
-        if config.commands.contains_key(&name) {
+        if config.current().contains_key(&name) {
             let override_existing = Confirm::new(&format!(
                 "Command name '{}' already exists. Do you want to override it?",
                 name
@@ -93,7 +135,10 @@ pub fn crawl_directory(dirs: Vec<&str>) -> Result<Vec<String>, InquireError> {
                 continue;
             }
         }
-        config.commands.insert(name.clone(), path.to_string());
+        config
+            .current_mut()
+            .insert(name.clone(), Entry::new(path.to_string()));
+        decide(&mut report, &path, Decision::Added, Some(name.clone()));
         // make sure it won't reappear
         if let Some(abs_path) = installer::read_shortcut(&path) {
             abs_skips.push(abs_path);
@@ -113,6 +158,7 @@ pub fn crawl_directory(dirs: Vec<&str>) -> Result<Vec<String>, InquireError> {
             if !selected.contains(&path) {
                 // make sure it won't reappear
                 config.skipped.push(path.to_string());
+                decide(&mut report, &path, Decision::Hidden, None);
                 if let Some(abs_path) = installer::read_shortcut(&path) {
                     abs_skips.push(abs_path);
                 }
@@ -121,12 +167,23 @@ pub fn crawl_directory(dirs: Vec<&str>) -> Result<Vec<String>, InquireError> {
     }
 
     match config.write() {
-        Ok(_) => return Ok(executables),
-        Err(err) => return Err(InquireError::IO(err)),
+        Ok(_) => Ok(report),
+        Err(err) => Err(InquireError::IO(err)),
+    }
+}
+
+fn decide(report: &mut CrawlReport, path: &str, decision: Decision, name: Option<String>) {
+    if let Some(candidate) = report
+        .candidates
+        .iter_mut()
+        .find(|candidate| candidate.path == path)
+    {
+        candidate.decision = decision;
+        candidate.name = name;
     }
 }
 
-fn file_exists(expanded_lnks: &Vec<String>, path: &str) -> Result<bool, io::Error> {
+fn file_exists(expanded_lnks: &[String], path: &str) -> Result<bool, io::Error> {
     if path.ends_with(".lnk") || path.ends_with(".url") {
         if let Some(expanded_path) = installer::read_shortcut(path) {
             let flag = expanded_lnks.contains(&expanded_path);
@@ -140,96 +197,326 @@ fn file_exists(expanded_lnks: &Vec<String>, path: &str) -> Result<bool, io::Erro
     Ok(false)
 }
 
-pub fn run() -> io::Result<()> {
-    // Get config
-    let config = installer::check()?;
-    let rhiza_src = tilde("~\\.rhiza\\src").to_string();
-    let rhiza_bin = tilde("~\\.rhiza\\bin").to_string();
+/// What `run` did to each command
+#[derive(Default, Serialize)]
+pub struct RunReport {
+    pub created: Vec<Created>,
+    /// Commands that were already linked
+    pub unchanged: Vec<String>,
+    pub skipped: Vec<Skipped>,
+}
+
+/// A command that got a new link in src
+#[derive(Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Created {
+    pub name: String,
+    pub link: PathBuf,
+}
+
+/// A command that couldn't be linked, with why
+#[derive(Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Skipped {
+    pub name: String,
+    pub reason: String,
+}
+
+/// Links the commands of `scope` into its src, bin and Start Menu directories
+pub fn run(scope: Scope) -> io::Result<RunReport> {
+    let mut report = RunReport::default();
+    // Get config, with the commands of the active profiles
+    let config = installer::check()?.applied();
+    scope.check_elevation()?;
+    hooks::run(&config, Hook::PreRun, scope, &[])?;
+    let rhiza_src = scope.src_dir();
+    let rhiza_bin = scope.bin_dir();
 
     // Ensure bin directory exists
     fs::create_dir_all(&rhiza_src)?;
+    if scope == Scope::Machine {
+        let mut store = RegistryEnv(scope);
+        if !path_env::contains(&store, &rhiza_bin)? {
+            path_env::add(&mut store, &rhiza_bin)?;
+        }
+    }
 
-    for (key, path) in config.commands.iter() {
+    for (key, entry) in config.commands.iter() {
+        // Shim-only commands get a shim straight to their target, without a link
+        if entry.scope != scope || entry.is_shim_only() {
+            continue;
+        }
+        let path = &entry.target;
         let source_path = Path::new(path);
 
         // Skip if source doesn't exist
         if !source_path.exists() {
-            println!(
+            say!(
                 "{}",
                 format!("Source {} doesn't exist, skipping...", path).red(),
             );
+            report.skipped.push(Skipped {
+                name: key.to_string(),
+                reason: "source doesn't exist".to_string(),
+            });
             continue;
         }
 
-        match source_path.extension().and_then(|ext| ext.to_str()) {
-            Some("url") | Some("lnk") => {
-                // For .url and .lnk files, we copy them
-                let ext = source_path.extension().unwrap().to_str().unwrap();
+        let kind = match TargetKind::detect(source_path) {
+            Some(kind) => kind,
+            None => {
+                say!(
+                    "{}",
+                    format!("Unsupported file type {}, skipping ...", path).yellow()
+                );
+                report.skipped.push(Skipped {
+                    name: key.to_string(),
+                    reason: "unsupported file type".to_string(),
+                });
+                continue;
+            }
+        };
+
+        match kind.launcher(source_path) {
+            Ok(Launcher::Copy(ext)) => {
+                // For .url, .lnk and .appref-ms files, we copy them
                 let target_name = format!("{}.{}", key, ext);
                 let target_path = Path::new(&rhiza_src).join(&target_name);
 
                 if !target_path.exists() {
-                    fs::copy(source_path, &target_path)?;
-                    println!(
+                    copy_link(key, source_path, &target_path, scope)?;
+                    say!(
                         "{} {} -> {}",
                         "Created".green(),
                         key.bold(),
                         source_path.display()
                     );
+                    report.created.push(Created {
+                        name: key.to_string(),
+                        link: target_path,
+                    });
+                } else {
+                    report.unchanged.push(key.to_string());
                 }
             }
-            Some("exe") => {
-                // For .exe files, we create a shortcut
+            Ok(Launcher::Shortcut { program, args }) => {
+                // Everything else gets a shortcut, scripts through their interpreter
                 let target_name = format!("{}.lnk", key);
                 let target_path = Path::new(&rhiza_src).join(&target_name);
 
                 if !target_path.exists() {
-                    create_shortcut(source_path, &target_path)?;
-                    println!(
+                    let icon = cache_icon(key, source_path, scope);
+                    create_shortcut(&program, args, source_path, &target_path, icon)?;
+                    say!(
                         "{} {} -> {}",
                         "Created shortcut".green(),
                         key,
                         source_path.display()
                     );
+                    report.created.push(Created {
+                        name: key.to_string(),
+                        link: target_path,
+                    });
+                } else {
+                    report.unchanged.push(key.to_string());
                 }
             }
-            _ => {
-                println!(
+            Err(err) => {
+                say!(
                     "{}",
-                    format!("Unsupported file type {}, skipping ...", path).yellow()
+                    format!("Can't link {}: {}, skipping...", path, err).red()
                 );
+                report.skipped.push(Skipped {
+                    name: key.to_string(),
+                    reason: err.to_string(),
+                });
             }
         }
     }
 
-    generate_batch_files(&rhiza_src, &rhiza_bin)?;
-    println!("{}", "Done writing bat files".purple());
-    installer::copy_src()?;
+    generate_shims(&config, scope, &rhiza_src, &rhiza_bin)?;
+    shell::generate(&config)?;
+    start_menu::sync(&config, scope)?;
+    outputs::sync(&config, scope)?;
+
+    report.created.sort();
+    report.unchanged.sort();
+    report.skipped.sort();
+
+    // Names separated by `;`, like the PATH
+    let created: Vec<&str> = report.created.iter().map(|c| c.name.as_str()).collect();
+    let skipped: Vec<&str> = report.skipped.iter().map(|s| s.name.as_str()).collect();
+    let vars = [
+        ("RHIZA_CREATED", created.join(";")),
+        ("RHIZA_SKIPPED", skipped.join(";")),
+    ];
+    hooks::run(&config, Hook::PostRun, scope, &vars)?;
+    Ok(report)
+}
+
+/// Writes `<src>\<key>.url`, refusing to replace an existing one unless `force` is set since
+/// it may be the only copy of another command
+pub fn create_url(
+    key: &str,
+    url: &str,
+    icon: Option<&str>,
+    icon_index: i32,
+    scope: Scope,
+    force: bool,
+) -> io::Result<String> {
+    if !url.contains(':') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "'{}' is not a url (expected a scheme such as https:// or steam://)",
+                url
+            ),
+        ));
+    }
+
+    scope.check_elevation()?;
+    let rhiza_src = scope.src_dir();
+    fs::create_dir_all(&rhiza_src)?;
+
+    let mut url_file = UrlFile::new(url);
+    if let Some(icon) = icon {
+        url_file.set_icon(icon, icon_index);
+    }
+
+    let target_path = Path::new(&rhiza_src).join(format!("{}.url", key));
+    refuse_overwrite(&target_path, force)?;
+    url_file.write(&target_path)?;
+    say!("{} {} -> {}", "Created".green(), key.bold(), url);
+
+    Ok(target_path.to_string_lossy().to_string())
+}
 
+fn refuse_overwrite(path: &Path, force: bool) -> io::Result<()> {
+    if path.exists() && !force {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!(
+                "{} already exists, pass --force to replace it",
+                path.display()
+            ),
+        ));
+    }
     Ok(())
 }
 
-pub fn remove_key(key: &str) -> io::Result<()> {
-    let rhiza_src = tilde("~\\.rhiza\\src").to_string();
-    let rhiza_bin = tilde("~\\.rhiza\\bin").to_string();
-    let start_menu = format!(
-        "{}\\AppData\\Roaming\\Microsoft\\Windows\\Start Menu\\Programs\\rhiza",
-        shellexpand::tilde("~").to_string()
+/// Writes `<src>\<key>.lnk`, refusing to replace an existing one unless `force` is set
+pub fn create_lnk(key: &str, link: &ShellLink, scope: Scope, force: bool) -> io::Result<String> {
+    scope.check_elevation()?;
+    let rhiza_src = scope.src_dir();
+    fs::create_dir_all(&rhiza_src)?;
+
+    let target_path = Path::new(&rhiza_src).join(format!("{}.lnk", key));
+    refuse_overwrite(&target_path, force)?;
+    link.write(&target_path)?;
+    say!(
+        "{} {} -> {}",
+        "Created shortcut".green(),
+        key.bold(),
+        link.target.display()
     );
 
+    Ok(target_path.to_string_lossy().to_string())
+}
+
+pub fn remove_key(config: &Config, key: &str, scope: Scope) -> io::Result<()> {
+    scope.check_elevation()?;
+    let rhiza_src = scope.src_dir();
+    let rhiza_bin = scope.bin_dir();
+
+    // Remove its Desktop and Startup copies while the link they match is still there
+    if let Ok(entries) = fs::read_dir(&rhiza_src) {
+        for entry in entries.flatten() {
+            if entry.path().file_stem().is_some_and(|stem| stem == key) {
+                outputs::remove(&entry.path(), scope)?;
+            }
+        }
+    }
+
     // Remove from src directory (could be .url, .lnk files)
     remove_from_directory(&rhiza_src, key)?;
 
     // Remove from bin directory (.bat files)
     remove_from_directory(&rhiza_bin, key)?;
 
-    // Remove from start menu
-    remove_from_directory(&start_menu, key)?;
+    let icon_path = icon::cache_path(key, scope);
+    if icon_path.exists() {
+        fs::remove_file(&icon_path)?;
+        say!("  {} {}", "Deleted".yellow(), icon_path.display());
+    }
+
+    // Remove from start menu, wherever its category put it
+    if Path::new(&rhiza_src).exists() {
+        start_menu::sync(config, scope)?;
+    }
+
+    say!("{} {}", "Removed".red(), key.bold());
+    Ok(())
+}
+
+/// Takes down what linking a command generated: its shim, Start Menu entry and Desktop and
+/// Startup copies. Its file in `src` only goes if `rhz run` made it, a link made by `rhz url`
+/// or `rhz lnk` is the command itself and stays for when it's applied again
+pub fn unlink_key(config: &Config, key: &str, entry: &Entry) -> io::Result<()> {
+    let scope = entry.scope;
+    scope.check_elevation()?;
+
+    if let Some(src) = linked_file(key, entry) {
+        outputs::remove(&src, scope)?;
+        if fs::canonicalize(&src).ok() != fs::canonicalize(&entry.target).ok() {
+            fs::remove_file(&src)?;
+            say!("  {} {}", "Deleted".yellow(), src.display());
+
+            let icon_path = icon::cache_path(key, scope);
+            if icon_path.exists() {
+                fs::remove_file(&icon_path)?;
+                say!("  {} {}", "Deleted".yellow(), icon_path.display());
+            }
+        }
+    }
+    remove_from_directory(&scope.bin_dir(), key)?;
 
-    println!("{} {}", "Removed".red(), key.bold());
+    if Path::new(&scope.src_dir()).exists() {
+        start_menu::sync(config, scope)?;
+    }
+
+    say!("{} {}", "Unlinked".yellow(), key.bold());
     Ok(())
 }
 
+/// Whether a command is `linked`, waits for `rhz run` (`pending`) or lost its target (`missing`)
+pub fn status(key: &str, entry: &Entry) -> &'static str {
+    if !Path::new(&entry.target).exists() {
+        "missing"
+    } else if entry.is_shim_only() {
+        let bin = PathBuf::from(entry.scope.bin_dir());
+        if ["shim", "bat"]
+            .iter()
+            .any(|ext| bin.join(format!("{}.{}", key, ext)).exists())
+        {
+            "linked"
+        } else {
+            "pending"
+        }
+    } else if linked_file(key, entry).is_some() {
+        "linked"
+    } else {
+        "pending"
+    }
+}
+
+/// The file linking to a command in the src directory of its scope
+fn linked_file(key: &str, entry: &Entry) -> Option<PathBuf> {
+    fs::read_dir(entry.scope.src_dir())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|file| file.path())
+        .find(|path| path.file_stem().is_some_and(|stem| stem == key))
+}
+
 fn remove_from_directory(dir: &str, key: &str) -> io::Result<()> {
     let dir_path = Path::new(dir);
     if !dir_path.exists() {
@@ -237,39 +524,112 @@ fn remove_from_directory(dir: &str, key: &str) -> io::Result<()> {
     }
 
     // Common file extensions that might exist for this key
-    let extensions = ["url", "lnk", "bat"];
+    let extensions = ["url", "lnk", "appref-ms", "bat", "exe", "shim"];
 
     for ext in &extensions {
         let filename = format!("{}.{}", key, ext);
         let file_path = dir_path.join(&filename);
         if file_path.exists() {
             fs::remove_file(&file_path)?;
-            println!("  {} {}", "Deleted".yellow(), file_path.display());
+            say!("  {} {}", "Deleted".yellow(), file_path.display());
         }
     }
 
     Ok(())
 }
 
-fn clean_dir(target: &Path) -> io::Result<()> {
-    if !target.exists() {
-        fs::create_dir_all(target)?;
-        return Ok(());
+/// Writes the launchers in `dir` whose content changed and removes the ones (`.exe`, `.shim`
+/// and `.bat` files) no command has anymore.
+///
+/// A running shim can't be replaced or removed on Windows, so that only warns and the next
+/// run takes care of it
+fn write_launchers(dir: &Path, files: &[(String, Vec<u8>)]) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+
+    let wanted: HashSet<String> = files.iter().map(|(name, _)| name.to_lowercase()).collect();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let is_launcher = path.extension().is_some_and(|ext| {
+            ["exe", "shim", "bat"].contains(&ext.to_string_lossy().to_lowercase().as_str())
+        });
+        if is_launcher && path.is_file() && !wanted.contains(&name) {
+            skip_in_use(fs::remove_file(&path), &path)?;
+        }
     }
 
-    for entry in fs::read_dir(target)? {
-        let entry = entry?;
-        let path = entry.path();
+    for (name, content) in files {
+        let path = dir.join(name);
+        if fs::read(&path).ok().as_ref() != Some(content) {
+            skip_in_use(fs::write(&path, content), &path)?;
+        }
+    }
+    Ok(())
+}
+
+fn skip_in_use(res: io::Result<()>, path: &Path) -> io::Result<()> {
+    match res {
+        // ERROR_SHARING_VIOLATION, the file is open, e.g a shim that is running
+        Err(err)
+            if err.kind() == io::ErrorKind::PermissionDenied || err.raw_os_error() == Some(32) =>
+        {
+            let msg = format!(
+                "{} is in use, leaving it until the next run",
+                path.display()
+            );
+            say!("{}", msg.yellow());
+            Ok(())
+        }
+        res => res,
+    }
+}
 
-        fs::remove_file(&path)?;
+/// Writes a launcher in `dst_dir` for every command of `scope` with a shim output
+pub fn generate_shims(
+    config: &Config,
+    scope: Scope,
+    src_dir: &str,
+    dst_dir: &str,
+) -> io::Result<()> {
+    let launchers = launched_files(config, scope, src_dir)?;
+    let shim_exe = match shim::shim_exe() {
+        Some(shim_exe) => shim_exe,
+        None => {
+            say!(
+                "{}",
+                "rhz-shim.exe wasn't found next to rhz, falling back to bat files".yellow()
+            );
+            generate_batch_files(config, &launchers, dst_dir)?;
+            say!("{}", "Done writing bat files".purple());
+            return Ok(());
+        }
+    };
+
+    let shim_exe = fs::read(shim_exe)?;
+    let mut files = Vec::new();
+    for (key, path) in &launchers {
+        let shim = Shim::for_entry(path, config.commands.get(key));
+
+        files.push((format!("{}.exe", key), shim_exe.clone()));
+        files.push((format!("{}.shim", key), shim.render().into_bytes()));
     }
+    write_launchers(Path::new(dst_dir), &files)?;
 
+    say!("{}", "Done writing shims".purple());
     Ok(())
 }
 
-fn generate_batch_files(src_dir: &str, dst_dir: &str) -> io::Result<()> {
-    let target = Path::new(dst_dir);
-    clean_dir(target)?;
+/// The files in `src_dir` of the commands with a shim, plus the targets of the shim-only
+/// commands of `scope`
+fn launched_files(
+    config: &Config,
+    scope: Scope,
+    src_dir: &str,
+) -> io::Result<Vec<(String, PathBuf)>> {
+    let mut launchers = Vec::new();
 
     for entry in fs::read_dir(src_dir)? {
         let entry = entry?;
@@ -277,21 +637,42 @@ fn generate_batch_files(src_dir: &str, dst_dir: &str) -> io::Result<()> {
 
         if path.is_file() {
             if let Some(filename) = path.file_stem() {
-                let bat_filename = format!("{}.bat", filename.to_string_lossy());
-                let bat_path = Path::new(dst_dir).join(bat_filename);
-
-                // Create .bat file content
-                let lnk_path = path.to_string_lossy();
-                let bat_content = format!("@echo off\nstart /B \"\" \"{}\" %*", lnk_path);
-
-                // Write .bat file
-                let mut bat_file = fs::File::create(bat_path)?;
-                bat_file.write_all(bat_content.as_bytes())?;
+                let key = filename.to_string_lossy().to_string();
+                let wanted = match config.commands.get(&key) {
+                    Some(entry) => entry.has(Output::Shim) && !entry.is_shim_only(),
+                    // Files rhiza doesn't know get a shim, those of inactive profiles don't
+                    None => !config.is_inactive(&key),
+                };
+                if wanted {
+                    launchers.push((key, path));
+                }
             }
         }
     }
 
-    Ok(())
+    for (key, entry) in &config.commands {
+        if entry.is_shim_only() && entry.has(Output::Shim) && entry.scope == scope {
+            launchers.push((key.to_string(), PathBuf::from(&entry.target)));
+        }
+    }
+
+    Ok(launchers)
+}
+
+fn generate_batch_files(
+    config: &Config,
+    launchers: &[(String, PathBuf)],
+    dst_dir: &str,
+) -> io::Result<()> {
+    let files: Vec<(String, Vec<u8>)> = launchers
+        .iter()
+        .map(|(key, path)| {
+            // The launch a shim would do, written as a bat file
+            let shim = Shim::for_entry(path, config.commands.get(key));
+            (format!("{}.bat", key), shim.render_bat().into_bytes())
+        })
+        .collect();
+    write_launchers(Path::new(dst_dir), &files)
 }
 
 fn is_executable(entry: &DirEntry, target_extensions: &[&str]) -> bool {
@@ -367,8 +748,97 @@ fn is_user_friendly(entry: &DirEntry) -> bool {
 
     true
 }
-fn create_shortcut(source: &Path, target: &Path) -> io::Result<()> {
-    let sl = mslnk::ShellLink::new(source).unwrap();
-    sl.create_lnk(target).unwrap();
+fn create_shortcut(
+    program: &Path,
+    args: Option<String>,
+    source: &Path,
+    target: &Path,
+    icon: Option<PathBuf>,
+) -> io::Result<()> {
+    let mut link = ShellLink::new(program);
+    link.icon_location = icon.map(|icon| icon.to_string_lossy().to_string());
+    // Run from the source's directory rather than the interpreter's
+    let working_dir = if source.is_dir() {
+        source
+    } else {
+        source.parent().unwrap_or(source)
+    };
+    link.working_dir = Some(working_dir.to_string_lossy().to_string());
+    link.arguments = args;
+    link.write(target)
+}
+
+/// Copies a .url or .lnk file, pointing the copy to a cached copy of its icon.
+/// Like a missing icon, a copy that can't be pointed to it only costs a warning
+fn copy_link(key: &str, source: &Path, target: &Path, scope: Scope) -> io::Result<()> {
+    fs::copy(source, target)?;
+    let icon = match cache_icon(key, source, scope) {
+        Some(icon) => icon.to_string_lossy().to_string(),
+        None => return Ok(()),
+    };
+
+    let pointed = match target.extension().and_then(|ext| ext.to_str()) {
+        Some("url") => UrlFile::read(target).and_then(|mut url_file| {
+            url_file.set_icon(&icon, 0);
+            url_file.write(target)
+        }),
+        Some("lnk") => fs::read(target)
+            .and_then(|bytes| lnk::set_icon(&bytes, &icon, 0))
+            .and_then(|bytes| fs::write(target, bytes)),
+        _ => Ok(()),
+    };
+    if let Err(err) = pointed {
+        say!(
+            "{}",
+            format!("Can't set the icon of {}: {}", target.display(), err).yellow()
+        );
+    }
     Ok(())
 }
+
+/// The icon of `source` in the icon cache, a missing icon only costs a warning
+fn cache_icon(key: &str, source: &Path, scope: Scope) -> Option<PathBuf> {
+    match icon::cache(key, source, scope) {
+        Ok(icon) => icon,
+        Err(err) => {
+            say!(
+                "{}",
+                format!("Can't extract the icon of {}: {}", source.display(), err).yellow()
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn launchers_are_updated_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let bin = dir.path();
+        fs::write(bin.join("old.exe"), "shim").unwrap();
+        fs::write(bin.join("old.shim"), "path = C:\\old.exe").unwrap();
+        fs::write(bin.join("KEPT.EXE"), "shim").unwrap();
+        fs::write(bin.join("notes.txt"), "mine").unwrap();
+
+        let files = [
+            ("kept.exe".to_string(), b"shim".to_vec()),
+            ("kept.shim".to_string(), b"path = C:\\kept.exe".to_vec()),
+        ];
+        write_launchers(bin, &files).unwrap();
+
+        assert!(!bin.join("old.exe").exists());
+        assert!(!bin.join("old.shim").exists());
+        // Launchers differing only in case are the same file on Windows, that one is kept
+        assert!(bin.join("KEPT.EXE").exists());
+        assert_eq!(fs::read(bin.join("kept.shim")).unwrap(), files[1].1);
+        assert!(bin.join("notes.txt").exists());
+
+        let files = [("kept.shim".to_string(), b"path = C:\\new.exe".to_vec())];
+        write_launchers(bin, &files).unwrap();
+        assert_eq!(fs::read(bin.join("kept.shim")).unwrap(), files[0].1);
+        assert!(!bin.join("KEPT.EXE").exists());
+    }
+}